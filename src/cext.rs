@@ -22,6 +22,16 @@ pub fn cstr_to_str(cs : *const c_char) -> String {
     str::from_utf8(unsafe { c_str_to_bytes(&cs) }).ok().unwrap().to_string()
 }
 
+/// Copies `len` bytes starting at `data` into an owned vector,
+/// a null pointer is read as no bytes
+pub fn bytes_to_vec(data : *const u8, len : usize) -> Vec<u8> {
+    if data.is_null() || len == 0 {
+        return Vec::new();
+    }
+    let bytes: &[u8] = unsafe { mem::transmute(Slice { data: data, len: len }) };
+    bytes.to_vec()
+}
+
 /// Converts a vector of rust strings to a vector of c strings
 pub fn strs_to_cstrs(strs : Vec<&str>) -> Vec<CString> {
     let result : Vec<CString> = strs.into_iter().map(|s : &str| { 
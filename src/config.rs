@@ -0,0 +1,384 @@
+/// Settings read from a configuration file and the environment
+
+use std::fmt;
+use std::num;
+use std::char;
+use std::os::{getenv};
+use std::io::{File, IoErrorKind};
+use osext::{get_env_as};
+use sqlite::{OpenMode};
+use keymap::{Keymap};
+use curses::{COLOR_DEFAULT, COLOR_BLACK, COLOR_RED, COLOR_GREEN, COLOR_YELLOW};
+use curses::{COLOR_BLUE, COLOR_MAGENTA, COLOR_CYAN, COLOR_WHITE};
+
+/// Where the file is looked for under the configuration directory
+static CONFIG_PATH : &'static str = "csg/config.toml";
+
+static COLOR_NAMES : [(&'static str, i16); 9] = [
+    ("default", COLOR_DEFAULT),
+    ("black", COLOR_BLACK),
+    ("red", COLOR_RED),
+    ("green", COLOR_GREEN),
+    ("yellow", COLOR_YELLOW),
+    ("blue", COLOR_BLUE),
+    ("magenta", COLOR_MAGENTA),
+    ("cyan", COLOR_CYAN),
+    ("white", COLOR_WHITE)
+];
+
+/// A configuration file that couldn't be read
+#[derive(Clone, PartialEq, Show)]
+pub struct ConfigError {
+    pub path : String,
+    /// Line the problem is on, 0 when it isn't about one line
+    pub line : usize,
+    pub message : String
+}
+
+impl fmt::String for ConfigError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "{}: {}", self.path, self.message),
+            line => write!(f, "{}:{}: {}", self.path, line, self.message)
+        }
+    }
+}
+
+/// Everything that can be configured
+#[derive(Clone)]
+pub struct Config {
+    /// Widest a column is sized to fit its contents
+    pub cell_width : usize,
+    /// Whether to use colors when the terminal has them
+    pub colors : bool,
+    /// Colors of NULLs, numbers and BLOBs
+    pub null_color : i16,
+    pub number_color : i16,
+    pub blob_color : i16,
+    /// How NULL is shown
+    pub null_text : String,
+    /// How databases are opened
+    pub mode : OpenMode,
+    /// Statements remembered by the SQL console
+    pub history_size : usize,
+    /// Separates the fields of exported CSV
+    pub csv_delimiter : char,
+    pub keymap : Keymap
+}
+
+impl Config {
+    /// Constructor for the built-in settings
+    pub fn new() -> Config {
+        Config {
+            cell_width : 32,
+            colors : true,
+            null_color : COLOR_BLUE,
+            number_color : COLOR_CYAN,
+            blob_color : COLOR_MAGENTA,
+            null_text : "<NULL>".to_string(),
+            mode : OpenMode::ReadWrite,
+            history_size : 100,
+            csv_delimiter : ',',
+            keymap : Keymap::new()
+        }
+    }
+
+    /// Reads the file at `path`, or the one under $XDG_CONFIG_HOME (falling
+    /// back to ~/.config) when there is no path. A missing file gives the
+    /// built-in settings unless it was asked for by path.
+    pub fn load(path : Option<&str>) -> Result<Config, ConfigError> {
+        let (path, required) = match path {
+            Some(path) => { (Path::new(path), true) },
+            None => {
+                match config_home() {
+                    Some(home) => { (home.join(CONFIG_PATH), false) },
+                    None => { return Ok(Config::new()); }
+                }
+            }
+        };
+        let shown = format!("{}", path.display());
+        match File::open(&path).and_then(|mut file| file.read_to_string()) {
+            Ok(text) => { Config::parse(text.as_slice(), shown.as_slice()) },
+            Err(ref err) if err.kind == IoErrorKind::FileNotFound && !required => { Ok(Config::new()) },
+            Err(err) => {
+                Err(ConfigError { path : shown, line : 0, message : format!("{}", err) })
+            }
+        }
+    }
+
+    /// Reads settings over the built-in ones. `path` is only used in errors.
+    ///
+    /// ```text
+    /// [display]
+    /// cell-width = 24
+    /// null = "∅"
+    /// colors = true
+    ///
+    /// [colors]
+    /// null = "blue"
+    /// number = "cyan"
+    /// blob = "magenta"
+    ///
+    /// [database]
+    /// mode = "read-only"
+    ///
+    /// [console]
+    /// history-size = 500
+    ///
+    /// [export]
+    /// delimiter = ";"
+    ///
+    /// [keys]
+    /// move-down = ["C-n", "Down"]
+    /// ```
+    pub fn parse(text : &str, path : &str) -> Result<Config, ConfigError> {
+        let mut config = Config::new();
+        let mut section = "".to_string();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message : String| ConfigError { path : path.to_string(), line : i + 1, message : message };
+            let line = strip_comment(line).trim();
+            if line.is_empty() || line.starts_with("#") {
+                continue;
+            }
+            if line.starts_with("[") {
+                if !line.ends_with("]") {
+                    return Err(error("expected `]`".to_string()));
+                }
+                section = line.slice(1, line.len() - 1).trim().to_string();
+                continue;
+            }
+            let result = match section.as_slice() {
+                // Lines here are handed over whole, values can be lists
+                "keys" => { config.keymap.load_line(line, i + 1) },
+                _ => { config.set(section.as_slice(), line) }
+            };
+            match result {
+                Ok(_) => { },
+                Err(message) => { return Err(error(message)); }
+            }
+        }
+        match config.keymap.check_overlaps() {
+            Ok(_) => { Ok(config) },
+            Err((line, message)) => {
+                Err(ConfigError { path : path.to_string(), line : line, message : message })
+            }
+        }
+    }
+
+    /// Applies a `key = value` line of `section`
+    fn set(&mut self, section : &str, line : &str) -> Result<(), String> {
+        let (key, value) = match line.find('=') {
+            Some(i) => { (line.slice_to(i).trim(), line.slice_from(i + 1).trim()) },
+            None => { return Err("expected `key = value`".to_string()); }
+        };
+        match (section, key) {
+            ("display", "cell-width") => {
+                match parse_number(value) {
+                    Ok(n) if n > 0 => { self.cell_width = n; },
+                    Ok(_) => { return Err("cell-width must be at least 1".to_string()); },
+                    Err(err) => { return Err(err); }
+                }
+            },
+            ("display", "null") => {
+                match parse_string(value) {
+                    Ok(text) => { self.null_text = text; },
+                    Err(err) => { return Err(err); }
+                }
+            },
+            ("display", "colors") => {
+                match value {
+                    "true" => { self.colors = true; },
+                    "false" => { self.colors = false; },
+                    _ => { return Err(format!("expected true or false, found `{}`", value)); }
+                }
+            },
+            ("colors", "null") | ("colors", "number") | ("colors", "blob") => {
+                let color = match parse_string(value).and_then(|name| parse_color(name.as_slice())) {
+                    Ok(color) => { color },
+                    Err(err) => { return Err(err); }
+                };
+                match key {
+                    "null" => { self.null_color = color; },
+                    "number" => { self.number_color = color; },
+                    _ => { self.blob_color = color; }
+                }
+            },
+            ("database", "mode") => {
+                match parse_string(value).and_then(|name| parse_mode(name.as_slice())) {
+                    Ok(mode) => { self.mode = mode; },
+                    Err(err) => { return Err(err); }
+                }
+            },
+            ("console", "history-size") => {
+                match parse_number(value) {
+                    Ok(n) => { self.history_size = n; },
+                    Err(err) => { return Err(err); }
+                }
+            },
+            ("export", "delimiter") => {
+                match parse_string(value).and_then(|text| parse_delimiter(text.as_slice())) {
+                    Ok(delimiter) => { self.csv_delimiter = delimiter; },
+                    Err(err) => { return Err(err); }
+                }
+            },
+            _ => {
+                return Err(match section {
+                    "" => format!("unknown setting `{}`", key),
+                    _ => format!("unknown setting `{}` in [{}]", key, section)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets the environment override the file: NO_COLOR turns colors off,
+    /// CSG_CELL_WIDTH, CSG_NULL and CSG_HISTORY_SIZE replace their settings
+    pub fn apply_env(&mut self) {
+        if getenv("NO_COLOR").map(|value| !value.is_empty()).unwrap_or(false) {
+            self.colors = false;
+        }
+        match get_env_as::<usize>("CSG_CELL_WIDTH") {
+            Some(n) if n > 0 => { self.cell_width = n; },
+            _ => { }
+        }
+        match getenv("CSG_NULL") {
+            Some(text) => { self.null_text = text; },
+            None => { }
+        }
+        match get_env_as::<usize>("CSG_HISTORY_SIZE") {
+            Some(n) => { self.history_size = n; },
+            None => { }
+        }
+    }
+}
+
+/// Directory configuration files are kept in
+fn config_home() -> Option<Path> {
+    match getenv("XDG_CONFIG_HOME") {
+        Some(ref dir) if !dir.is_empty() => { return Some(Path::new(dir.as_slice())); },
+        _ => { }
+    }
+    getenv("HOME").map(|home| Path::new(home).join(".config"))
+}
+
+/// Reads a color by name
+fn parse_color(name : &str) -> Result<i16, String> {
+    match COLOR_NAMES.iter().find(|&&(n, _)| n == name) {
+        Some(&(_, color)) => { Ok(color) },
+        None => { Err(format!("unknown color `{}`", name)) }
+    }
+}
+
+/// Reads an open mode by name
+fn parse_mode(name : &str) -> Result<OpenMode, String> {
+    match name {
+        "read-only" => Ok(OpenMode::ReadOnly),
+        "read-write" => Ok(OpenMode::ReadWrite),
+        "create" => Ok(OpenMode::Create),
+        _ => Err(format!("expected read-only, read-write or create, found `{}`", name))
+    }
+}
+
+/// Reads a field delimiter, a single character or "tab"
+fn parse_delimiter(text : &str) -> Result<char, String> {
+    if text == "tab" {
+        return Ok('\t');
+    }
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some('"'), None) | (Some('\r'), None) | (Some('\n'), None) => {
+            Err(format!("`{}` can't separate fields", text))
+        },
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("expected a single character or tab, found `{}`", text))
+    }
+}
+
+/// Reads a whole number
+fn parse_number(value : &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Some(n) => Ok(n),
+        None => Err(format!("expected a number, found `{}`", value))
+    }
+}
+
+/// Reads a single quoted string
+fn parse_string(value : &str) -> Result<String, String> {
+    if value.starts_with("[") {
+        return Err("expected a quoted string, not a list".to_string());
+    }
+    parse_strings(value).map(|mut strings| strings.pop().unwrap_or("".to_string()))
+}
+
+/// `line` up to a `#` that isn't inside a string
+pub fn strip_comment(line : &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => { escaped = false; },
+            '\\' if quoted => { escaped = true; },
+            '"' => { quoted = !quoted; },
+            '#' if !quoted => { return line.slice_to(i); },
+            _ => { }
+        }
+    }
+    line
+}
+
+/// Reads a quoted string or a bracketed list of them. Strings take the
+/// escapes of TOML basic strings: \n, \t, \r, \b, \f, \", \\ and
+/// \uXXXX or \UXXXXXXXX for any other character.
+pub fn parse_strings(value : &str) -> Result<Vec<String>, String> {
+    let bracketed = value.starts_with("[");
+    let mut chars = value.chars().skip(if bracketed { 1 } else { 0 });
+    let mut strings : Vec<String> = Vec::new();
+    loop {
+        // Between strings
+        match chars.by_ref().skip_while(|c| c.is_whitespace() || (bracketed && *c == ',')).next() {
+            Some('"') => { },
+            Some(']') if bracketed => { break; },
+            Some(c) => { return Err(format!("expected a quoted string, found `{}`", c)); },
+            None if bracketed => { return Err("expected `]`".to_string()); },
+            None if strings.is_empty() => { return Err("expected a quoted string".to_string()); },
+            None => { break; }
+        }
+        let mut string = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => { break; },
+                Some('\\') => {
+                    let c = match chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\x08',
+                        Some('f') => '\x0c',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(u @ 'u') | Some(u @ 'U') => {
+                            let digits : String = chars.by_ref().take(if u == 'u' { 4 } else { 8 }).collect();
+                            match num::from_str_radix::<u32>(digits.as_slice(), 16).and_then(char::from_u32) {
+                                Some(c) => c,
+                                None => { return Err(format!("bad escape `\\{}{}`", u, digits)); }
+                            }
+                        },
+                        Some(c) => { return Err(format!("unknown escape `\\{}`", c)); },
+                        None => { return Err("unterminated string".to_string()); }
+                    };
+                    string.push(c);
+                },
+                Some(c) => { string.push(c); },
+                None => { return Err("unterminated string".to_string()); }
+            }
+        }
+        strings.push(string);
+        if !bracketed {
+            match chars.find(|c| !c.is_whitespace()) {
+                Some(c) => { return Err(format!("unexpected `{}` after the string", c)); },
+                None => { break; }
+            }
+        }
+    }
+    Ok(strings)
+}
@@ -1,19 +1,113 @@
 use osext::{get_env_as};
-use sqlite::{Sqlite, exec_results};
-use curses::{Window, Curses};
+use std::cmp;
+use std::num;
+use std::io::{File, BufferedWriter};
+use std::io::fs::{PathExtensions};
+use sqlite::{Sqlite, SqliteError, ExecResult, Value, OpenMode, SchemaObject, ObjectKind, RowIdentity};
+use curses::{Window, Curses, Attr, Key, set_cursor_visible, color_pair};
+use curses::{A_NORMAL, A_BOLD, A_DIM, A_REVERSE, A_UNDERLINE};
+use curses::{COLOR_DEFAULT};
+use pager::{TablePager};
+use editor::{LineEditor, EditEvent};
+use keymap::{Keymap, Action};
+use config::{Config};
+use json::{pretty};
+use csv::{CsvWriter};
 
-// Keys
+// Key codes of the default bindings
 pub const KEY_q : usize = 113;
 pub const KEY_h : usize = 104;
 pub const KEY_j : usize = 106;
 pub const KEY_k : usize = 107;
 pub const KEY_l : usize = 108;
 pub const KEY_e : usize = 101;
+pub const KEY_i : usize = 105;
+pub const KEY_d : usize = 100;
+pub const KEY_w : usize = 119;
+pub const KEY_colon : usize = 58;
+pub const KEY_s : usize = 115;
+pub const KEY_dot : usize = 46;
 
 // Dimensions
 pub static DEFAULT_WIDTH : usize = 80;
 pub static DEFAULT_HEIGHT : usize = 40;
-pub static CELL_WIDTH : usize = 32;
+
+// Columns of the schema screen, enough for the widest section
+static SCHEMA_COLS : usize = 5;
+
+// Column widths: the narrowest a column gets, the blank columns after each,
+// how many rows are looked at to size them and how far a key resizes one
+static MIN_CELL_WIDTH : usize = 4;
+static COLUMN_GAP : usize = 1;
+static SAMPLE_ROWS : usize = 100;
+static RESIZE_STEP : usize = 2;
+
+// Color pairs
+const PAIR_NULL : i16 = 1;
+const PAIR_NUMBER : i16 = 2;
+const PAIR_BLOB : i16 = 3;
+
+/// How each part of a screen is drawn
+#[derive(Clone)]
+pub struct Theme {
+    pub header : Attr,
+    pub selected : Attr,
+    pub null : Attr,
+    pub number : Attr,
+    pub text : Attr,
+    pub blob : Attr,
+    /// Shown in place of NULL
+    pub null_text : String,
+    /// Widest a column is sized to fit its contents
+    pub cell_width : usize
+}
+
+impl Theme {
+    /// Theme for `config`, in color when it allows and the terminal has them
+    pub fn new(config : &Config, curses : &Curses) -> Theme {
+        match config.colors && curses.start_colors() {
+            true => Theme::colored(config, curses),
+            false => Theme::monochrome(config)
+        }
+    }
+
+    /// Attributes only, for terminals without colors
+    pub fn monochrome(config : &Config) -> Theme {
+        Theme {
+            header : A_BOLD | A_UNDERLINE,
+            selected : A_REVERSE,
+            null : A_DIM,
+            number : A_NORMAL,
+            text : A_NORMAL,
+            blob : A_DIM,
+            null_text : config.null_text.clone(),
+            cell_width : config.cell_width
+        }
+    }
+
+    /// Colors for each storage class, sets up the color pairs it uses
+    pub fn colored(config : &Config, curses : &Curses) -> Theme {
+        curses.init_pair(PAIR_NULL, config.null_color, COLOR_DEFAULT);
+        curses.init_pair(PAIR_NUMBER, config.number_color, COLOR_DEFAULT);
+        curses.init_pair(PAIR_BLOB, config.blob_color, COLOR_DEFAULT);
+        Theme {
+            null : color_pair(PAIR_NULL),
+            number : color_pair(PAIR_NUMBER),
+            blob : color_pair(PAIR_BLOB),
+            .. Theme::monochrome(config)
+        }
+    }
+
+    /// Attributes for showing `value`
+    pub fn value(&self, value : &Value) -> Attr {
+        match *value {
+            Value::Null => self.null,
+            Value::Integer(_) | Value::Real(_) => self.number,
+            Value::Text(_) => self.text,
+            Value::Blob(_) => self.blob
+        }
+    }
+}
 
 /// Represents a Window with a connection to a database
 #[derive(Clone)]
@@ -21,6 +115,8 @@ pub struct CSGWindow {
     sqlite : Sqlite,
 	window : Window,
     text : String,
+    /// Attributes the text is drawn with
+    attr : Attr,
     selectable: bool
 }
 
@@ -38,257 +134,1247 @@ impl CSGWindow {
 								begin_y,
 								begin_x),
 			text: "".to_string(),
+			attr: A_NORMAL,
 			selectable: selectable
 		}
 	}
 	/// Set window text
 	pub fn set_text(&mut self, s : String) {
-		self.text = s.clone();
-		self.window.write(s.as_slice());
+		self.set_styled_text(s, A_NORMAL);
 	}
-	/// Emphasizes text
-	pub fn highlight(s : String) -> String {
-		["*".to_string(), s, "*".to_string()].concat()
+	/// Set window text, drawn with attributes `attr`
+	pub fn set_styled_text(&mut self, s : String, attr : Attr) {
+		self.text = s;
+		self.attr = attr;
+		self.window.write_with(self.text.as_slice(), attr);
 	}
-	/// Designates a window as selected
-    pub fn select(&self) {
-        self.window.write(CSGWindow::highlight(self.text.clone()).as_slice())
+	/// Get method for window text
+	pub fn get_text(&self) -> &str {
+		self.text.as_slice()
+	}
+	/// Designates a window as selected, adding attributes `highlight`
+    pub fn select(&self, highlight : Attr) {
+        self.window.write_with(self.text.as_slice(), self.attr | highlight)
     }
 
     /// Undesignates a window as selected
     pub fn unselect(&self) {
-        self.window.write(self.text.as_slice())
+        self.window.write_with(self.text.as_slice(), self.attr)
     }
 }
 
 /// Represents the type of view
 #[derive(Clone)]
 pub enum ScreenKind {
+    DatabaseList,
     TableList,
-    TableDump
+    TableDump,
+    QueryResult,
+    /// One row per column of a table, for typing a new row
+    InsertForm,
+    /// Results of the statements typed at the SQL prompt
+    Console,
+    /// Columns, indexes, foreign keys and triggers of a table
+    Schema,
+    /// The whole of one value, a line per row
+    CellView
+}
+
+/// What to do with the text typed into a prompt
+#[derive(Clone)]
+enum PromptAction {
+    /// Store the text in the cell at (column, row), which held the value
+    EditCell(usize, usize, Value),
+    /// Delete the row if the answer is yes
+    DeleteRow(usize),
+    /// Run the text as SQL
+    RunSql,
+    /// Move to the line of a hex dump holding the offset typed
+    GoToOffset,
+    /// Write the rows of the screen to the file named
+    Export,
+    /// Write them over the file named, which exists, if the answer is yes
+    Overwrite(String)
+}
+
+/// A line being typed on the status line of the active screen
+#[derive(Clone)]
+struct Prompt {
+    label : String,
+    editor : LineEditor,
+    action : PromptAction
+}
+
+/// Text a value is edited as, NULL starts out empty
+fn edit_text(value : &Value) -> String {
+    match *value {
+        Value::Null => "".to_string(),
+        _ => value.to_text()
+    }
+}
+
+/// Cells of one row of text, padded out to `SCHEMA_COLS`
+fn text_row(cells : Vec<String>) -> Vec<Value> {
+    let mut row : Vec<Value> = cells.into_iter().map(|cell| Value::Text(cell)).collect();
+    while row.len() < SCHEMA_COLS {
+        row.push(Value::Text("".to_string()));
+    }
+    row
+}
+
+/// Terminal columns `c` takes up: none for combining marks and control
+/// characters, two for wide East Asian characters and emoji, one otherwise
+fn char_width(c : char) -> usize {
+    match c as u32 {
+        0x00...0x1f | 0x7f...0x9f => 0,
+        0x0300...0x036f | 0x200b...0x200f | 0xfe00...0xfe0f => 0,
+        0x1100...0x115f | 0x2e80...0x303e | 0x3041...0x33ff | 0x3400...0x4dbf |
+        0x4e00...0x9fff | 0xa000...0xa4cf | 0xac00...0xd7a3 | 0xf900...0xfaff |
+        0xfe30...0xfe4f | 0xff00...0xff60 | 0xffe0...0xffe6 | 0x1f300...0x1f64f |
+        0x1f900...0x1f9ff | 0x20000...0x3fffd => 2,
+        _ => 1
+    }
+}
+
+/// Terminal columns `text` takes up
+fn text_width(text : &str) -> usize {
+    text.chars().map(char_width).fold(0, |total, width| total + width)
+}
+
+/// `text` cut down to `width` terminal columns, ending in an ellipsis when
+/// it doesn't fit
+fn truncate(text : String, width : usize) -> String {
+    if text_width(text.as_slice()) <= width {
+        return text;
+    }
+    // Leave a column for the marker
+    let room = if width > 0 { width - 1 } else { 0 };
+    let mut cut = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        if used + char_width(c) > room {
+            break;
+        }
+        used += char_width(c);
+        cut.push(c);
+    }
+    if width > 0 {
+        cut.push('…');
+    }
+    cut
+}
+
+/// Bytes on each line of a hex dump fitting in `width`, the most that fit
+/// of 16, 8 and 4
+fn hex_per_line(width : usize) -> usize {
+    // Offset, hex with a space every eight bytes and the bytes as ASCII
+    let line_width = |n : usize| 9 + 3 * n + (n + 7) / 8 + n + 2;
+    [16, 8].iter().map(|n| *n).find(|n| line_width(*n) <= width).unwrap_or(4)
+}
+
+/// Lines of a hex dump of `bytes`, each giving the offset, `per_line` bytes
+/// in hex and the same bytes as ASCII with dots for the rest
+fn hex_lines(bytes : &[u8], per_line : usize) -> Vec<String> {
+    let mut lines : Vec<String> = Vec::new();
+    for (i, chunk) in bytes.chunks(per_line).enumerate() {
+        let mut line = format!("{:08x} ", i * per_line);
+        for j in (0..per_line) {
+            if j % 8 == 0 {
+                line.push(' ');
+            }
+            match chunk.get(j) {
+                Some(byte) => { line.push_str(format!("{:02x} ", byte).as_slice()); },
+                None => { line.push_str("   "); }
+            }
+        }
+        line.push('|');
+        for &byte in chunk.iter() {
+            line.push(if byte >= 0x20 && byte < 0x7f { byte as char } else { '.' });
+        }
+        line.push('|');
+        lines.push(line);
+    }
+    lines
+}
+
+/// Reads an offset typed in hex, with or without a leading 0x
+fn parse_offset(text : &str) -> Option<usize> {
+    let text = text.trim();
+    let digits = if text.starts_with("0x") || text.starts_with("0X") { text.slice_from(2) } else { text };
+    num::from_str_radix::<usize>(digits, 16)
+}
+
+/// Rows of a cell viewer showing `value`, one per line with JSON laid out
+/// over several and long lines wrapped at `width`. BLOBs are dumped in hex.
+/// Also returns whether the value was JSON.
+fn view_rows(value : &Value, width : usize) -> (Vec<Vec<Value>>, bool) {
+    let text = match *value {
+        Value::Null => { return (vec![vec![Value::Null]], false); },
+        Value::Blob(ref bytes) => {
+            let mut rows : Vec<Vec<Value>> = hex_lines(bytes.as_slice(), hex_per_line(width))
+                .into_iter().map(|line| vec![Value::Text(line)]).collect();
+            if rows.is_empty() {
+                rows.push(vec![Value::Text("".to_string())]);
+            }
+            return (rows, false);
+        },
+        _ => value.to_text()
+    };
+    let (text, is_json) = match pretty(text.as_slice()) {
+        Some(laid_out) => (laid_out, true),
+        None => (text, false)
+    };
+    let width = cmp::max(width, 1);
+    let mut rows : Vec<Vec<Value>> = Vec::new();
+    for line in text.replace("\t", "    ").split('\n') {
+        // Wrapped by terminal columns, a wide character that won't fit
+        // moves to the next row whole
+        let mut row = String::new();
+        let mut used = 0;
+        for c in line.chars().filter(|c| *c != '\r') {
+            if used + char_width(c) > width && !row.is_empty() {
+                rows.push(vec![Value::Text(row)]);
+                row = String::new();
+                used = 0;
+            }
+            used += char_width(c);
+            row.push(c);
+        }
+        rows.push(vec![Value::Text(row)]);
+    }
+    (rows, is_json)
+}
+
+/// "yes" or nothing, for flags shown in a grid
+fn yes(flag : bool) -> String {
+    match flag {
+        true => "yes".to_string(),
+        false => "".to_string()
+    }
 }
 
-/// Represents a view to be displayed
+/// Whether the answer to a question was yes
+fn is_yes(text : &str) -> bool {
+    match text.trim().to_lowercase().as_slice() {
+        "y" | "yes" => true,
+        _ => false
+    }
+}
+
+/// Turns edited text back into a value. Text stays text, anything else is
+/// read as a number when it looks like one.
+fn parse_like(text : String, like : &Value) -> Value {
+    match *like {
+        Value::Text(_) => { return Value::Text(text); },
+        Value::Null if text.is_empty() => { return Value::Null; },
+        _ => { }
+    }
+    match text.parse::<i64>() {
+        Some(n) => { return Value::Integer(n); },
+        None => { }
+    }
+    match text.parse::<f64>() {
+        Some(f) => { Value::Real(f) },
+        None => { Value::Text(text) }
+    }
+}
+
+/// Where the rows of a screen come from
+#[derive(Clone)]
+enum RowSource {
+    /// Rows held in memory
+    Rows(Vec<Vec<Value>>),
+    /// Rows read from a table as they come into view
+    Table(TablePager)
+}
+
+/// Represents a view to be displayed, a grid of cells seen through a
+/// viewport that scrolls to keep the selected cell visible
 #[derive(Clone)]
 pub struct CSGScreen {
     sqlite : Sqlite,
-    rows : usize,
-    cols : usize,
-    windows : Vec<Vec<Option<CSGWindow>>>,
+    kind : ScreenKind,
+    /// Shown on the status line
+    title : String,
+    /// Size of the area the screen draws in
+    width : usize,
+    height : usize,
+    /// Widest a column is sized to fit its contents
+    max_width : usize,
+    /// Width of each column of data
+    col_widths : Vec<usize>,
+    /// Column names, pinned above the cells when present
+    header : Option<Vec<String>>,
+    /// Values of each row
+    source : RowSource,
+    /// Windows for the column names
+    header_windows : Vec<CSGWindow>,
+    /// Windows for the visible cells, indexed [column][row] within the viewport
+    windows : Vec<Vec<CSGWindow>>,
+    /// Bottom line, shows where the selection is
+    status_window : CSGWindow,
+    /// Data coordinates (column, row) of the top left visible cell
+    offset : (usize, usize),
+    /// Data coordinates (column, row) of the selected cell
     active_window : (usize, usize),
-    kind : ScreenKind
+    /// Objects listed on a table list, one per row
+    objects : Vec<SchemaObject>,
+    /// How rows on a table dump are identified
+    identity : RowIdentity,
+    /// Whether cells on this screen may be changed
+    read_only : bool,
+    /// Value shown whole on a cell viewer
+    viewed : Option<Value>,
+    /// How cells are drawn
+    theme : Theme
 }
 
 impl CSGScreen {
-    /// Initializes a 2D vector of windows to all None
-    fn init_windows(rows : usize, cols : usize) -> Vec<Vec<Option<CSGWindow>>> {
-        let mut windows : Vec<Vec<Option<CSGWindow>>> = Vec::new();
-        for _ in (0..rows) {
-            let mut row : Vec<Option<CSGWindow>> = Vec::new();
-            for _ in (0..cols) {
-                row.push(None);
+    // Builds a screen of cells and draws it
+    fn new_grid(sqlite : Sqlite,
+                kind : ScreenKind,
+                title : String,
+                width : usize,
+                height : usize,
+                theme : &Theme,
+                max_width : usize,
+                header : Option<Vec<String>>,
+                source : RowSource) -> Result<CSGScreen, SqliteError> {
+        let status_window = CSGWindow::new(sqlite.clone(), false, 1, width, height - 1, 0);
+        let mut screen = CSGScreen {
+            sqlite : sqlite,
+            kind : kind,
+            title : title,
+            width : width,
+            height : height,
+            max_width : max_width,
+            col_widths : Vec::new(),
+            header : header,
+            source : source,
+            header_windows : Vec::new(),
+            windows : Vec::new(),
+            status_window : status_window,
+            offset : (0, 0),
+            active_window : (0, 0),
+            objects : Vec::new(),
+            identity : RowIdentity::Nothing,
+            read_only : true,
+            viewed : None,
+            theme : theme.clone()
+        };
+        screen.layout();
+        match screen.load_visible() {
+            Ok(_) => { },
+            Err(err) => { return Err(err) }
+        };
+        screen.fit_columns();
+        screen.write_all();
+        Ok(screen)
+    }
+
+    // Factory constructor for table dump screens
+    fn new_table_dump(sqlite : Sqlite, width : usize, height : usize, theme : &Theme, object : SchemaObject) -> Result<CSGScreen, SqliteError> {
+        let pager = match TablePager::new(sqlite.clone(), object.name.as_slice()) {
+            Ok(pager) => { pager },
+            Err(err) => { return Err(err) }
+        };
+        let identity = pager.identity().clone();
+        let header = pager.col_names().clone();
+        let read_only = object.is_read_only() || sqlite.is_read_only();
+        let mut screen = match CSGScreen::new_grid(sqlite, ScreenKind::TableDump, object.name.clone(),
+                                                   width, height, theme, theme.cell_width, Some(header),
+                                                   RowSource::Table(pager)) {
+            Ok(screen) => { screen },
+            Err(err) => { return Err(err) }
+        };
+        screen.identity = identity;
+        screen.read_only = read_only;
+        Ok(screen)
+    }
+
+    // Factory constructor for the form a new row of `table` is typed into,
+    // each column starts out with its default
+    fn new_insert_form(sqlite : Sqlite, width : usize, height : usize, theme : &Theme, table : &str) -> Result<CSGScreen, SqliteError> {
+        let columns = match sqlite.table_info(table) {
+            Ok(columns) => { columns },
+            Err(err) => { return Err(err); }
+        };
+        let mut cells : Vec<Vec<Value>> = Vec::new();
+        for column in columns.into_iter() {
+            let value = match column.default {
+                Some(ref expr) => {
+                    match sqlite.eval_default(expr.as_slice()) {
+                        Ok(value) => { value },
+                        Err(err) => { return Err(err); }
+                    }
+                },
+                None => { Value::Null }
+            };
+            cells.push(vec![Value::Text(column.name), Value::Text(column.decl_type), value]);
+        }
+        let header = vec!["column".to_string(), "type".to_string(), "value".to_string()];
+        let mut screen = match CSGScreen::new_grid(sqlite, ScreenKind::InsertForm, format!("new row in {}", table),
+                                                   width, height, theme, theme.cell_width, Some(header),
+                                                   RowSource::Rows(cells)) {
+            Ok(screen) => { screen },
+            Err(err) => { return Err(err); }
+        };
+        // Start on the first value
+        screen.active_window = (2, 0);
+        screen.write_all();
+        screen.read_only = false;
+        Ok(screen)
+    }
+
+    // Factory constructor for screens showing the results of arbitrary SQL
+    fn new_query_result(sqlite : Sqlite, width : usize, height : usize, theme : &Theme, sql : &str) -> Result<CSGScreen, SqliteError> {
+        let results = match sqlite.exec(sql) {
+            Ok(results) => { results },
+            Err(err) => { return Err(err) }
+        };
+        CSGScreen::new_result_grid(sqlite, width, height, theme, results,
+                                   ScreenKind::QueryResult, sql.to_string())
+    }
+
+    // Factory constructor for the schema of a table or view. Each section
+    // starts with a row naming its columns and is followed by a blank row.
+    fn new_schema(sqlite : Sqlite, width : usize, height : usize, theme : &Theme, name : &str) -> Result<CSGScreen, SqliteError> {
+        let columns = match sqlite.table_info(name) {
+            Ok(columns) => { columns },
+            Err(err) => { return Err(err); }
+        };
+        let indexes = match sqlite.indexes(name) {
+            Ok(indexes) => { indexes },
+            Err(err) => { return Err(err); }
+        };
+        let foreign_keys = match sqlite.foreign_keys(name) {
+            Ok(foreign_keys) => { foreign_keys },
+            Err(err) => { return Err(err); }
+        };
+        let triggers = match sqlite.triggers(name) {
+            Ok(triggers) => { triggers },
+            Err(err) => { return Err(err); }
+        };
+        let sql = match sqlite.object_sql(name) {
+            Ok(sql) => { sql },
+            Err(err) => { return Err(err); }
+        };
+
+        let mut cells : Vec<Vec<Value>> = Vec::new();
+        cells.push(text_row(vec!["column".to_string(), "type".to_string(), "not null".to_string(),
+                                 "default".to_string(), "primary key".to_string()]));
+        for column in columns.into_iter() {
+            cells.push(text_row(vec![column.name, column.decl_type, yes(column.not_null),
+                                     column.default.unwrap_or("".to_string()),
+                                     match column.pk { 0 => "".to_string(), pk => format!("{}", pk) }]));
+        }
+        if !indexes.is_empty() {
+            cells.push(text_row(Vec::new()));
+            cells.push(text_row(vec!["index".to_string(), "unique".to_string(), "columns".to_string()]));
+            for index in indexes.into_iter() {
+                cells.push(text_row(vec![index.name, yes(index.unique), index.columns.connect(", ")]));
+            }
+        }
+        if !foreign_keys.is_empty() {
+            cells.push(text_row(Vec::new()));
+            cells.push(text_row(vec!["foreign key".to_string(), "references".to_string(),
+                                     "on update".to_string(), "on delete".to_string()]));
+            for key in foreign_keys.into_iter() {
+                let references = match key.to.is_empty() {
+                    true => key.table,
+                    false => format!("{}({})", key.table, key.to.connect(", "))
+                };
+                cells.push(text_row(vec![key.columns.connect(", "), references, key.on_update, key.on_delete]));
             }
-            windows.push(row);
         }
-        windows
+        if !triggers.is_empty() {
+            cells.push(text_row(Vec::new()));
+            cells.push(text_row(vec!["trigger".to_string(), "sql".to_string()]));
+            for (trigger, trigger_sql) in triggers.into_iter() {
+                cells.push(text_row(vec![trigger, trigger_sql]));
+            }
+        }
+        match sql {
+            Some(sql) => {
+                cells.push(text_row(Vec::new()));
+                cells.push(text_row(vec!["create".to_string(), sql]));
+            },
+            None => { }
+        }
+        CSGScreen::new_grid(sqlite, ScreenKind::Schema, format!("schema of {}", name),
+                            width, height, theme, theme.cell_width, None, RowSource::Rows(cells))
     }
 
-    // Factory constructor for table dump screens
-    fn new_table_dump(sqlite : Sqlite, width : usize, height : usize, table : String) -> Result<CSGScreen, String> {
-        match sqlite.dump_table(table) {
+    // Factory constructor for an SQL console, empty until something is run
+    fn new_console(sqlite : Sqlite, width : usize, height : usize, theme : &Theme) -> Result<CSGScreen, SqliteError> {
+        CSGScreen::new_grid(sqlite, ScreenKind::Console, "sql".to_string(), width, height, theme, theme.cell_width,
+                            Some(Vec::new()), RowSource::Rows(Vec::new()))
+    }
+
+    // Lays out a result set as a grid under its column names
+    fn new_result_grid(sqlite : Sqlite,
+                       width : usize,
+                       height : usize,
+                       theme : &Theme,
+                       results : ExecResult,
+                       kind : ScreenKind,
+                       title : String) -> Result<CSGScreen, SqliteError> {
+        let ExecResult { rows, col_names, .. } = results;
+        CSGScreen::new_grid(sqlite, kind, title, width, height, theme, theme.cell_width,
+                            Some(col_names.unwrap_or(Vec::new())), RowSource::Rows(rows))
+    }
+
+    // Factory constructor for a viewer of the whole of `value`, found at
+    // `name`, wrapped to the width of the screen
+    fn new_cell_view(sqlite : Sqlite, width : usize, height : usize, theme : &Theme, name : String, value : Value) -> Result<CSGScreen, SqliteError> {
+        let (rows, is_json) = view_rows(&value, width);
+        let title = format!("{}  {}, {} bytes{}", name, value.storage_class(), value.byte_len(),
+                            if is_json { ", JSON" } else { "" });
+        let mut screen = match CSGScreen::new_grid(sqlite, ScreenKind::CellView, title,
+                                                   width, height, theme, width, None, RowSource::Rows(rows)) {
+            Ok(screen) => { screen },
+            Err(err) => { return Err(err); }
+        };
+        screen.viewed = Some(value);
+        Ok(screen)
+    }
+
+    // Factory constructor for the list of open databases
+    fn new_database_list(sqlite : Sqlite, width : usize, height : usize, theme : &Theme, filenames : &[String]) -> Result<CSGScreen, SqliteError> {
+        let cells : Vec<Vec<Value>> = filenames.iter().map(|f| vec![Value::Text(f.clone())]).collect();
+        CSGScreen::new_grid(sqlite, ScreenKind::DatabaseList, "databases".to_string(),
+                            width, height, theme, width, None, RowSource::Rows(cells))
+    }
+
+    // Factory constructor for table list screens, listing every schema object
+    // grouped by kind. Rows of tables are counted as they come into view.
+    fn new_table_list(sqlite : Sqlite, width : usize, height : usize, theme : &Theme, show_internal : bool) -> Result<CSGScreen, SqliteError> {
+        let objects : Vec<SchemaObject> = match sqlite.list_schema() {
+            Ok(objects) => { objects.into_iter().filter(|object| show_internal || !object.is_internal()).collect() },
+            Err(err) => { return Err(err); }
+        };
+        let cells : Vec<Vec<Value>> = objects.iter().map(|object| {
+            vec![Value::Text(object.name.clone()),
+                 Value::Text(object.kind.name().to_string()),
+                 match object.kind {
+                     // Not counted yet
+                     ObjectKind::Table => Value::Null,
+                     _ => Value::Text("".to_string())
+                 }]
+        }).collect();
+        let header = vec!["name".to_string(), "type".to_string(), "rows".to_string()];
+        let mut screen = match CSGScreen::new_grid(sqlite, ScreenKind::TableList, "tables".to_string(),
+                                                   width, height, theme, theme.cell_width, Some(header), RowSource::Rows(cells)) {
+            Ok(screen) => { screen },
+            Err(err) => { return Err(err); }
+        };
+        screen.objects = objects;
+        match screen.load_visible() {
             Ok(_) => { },
-            Err(msg) => { return Err(msg) }
+            Err(err) => { return Err(err); }
         };
+        // Make room for the counts
+        screen.fit_columns();
+        screen.write_all();
+        Ok(screen)
+    }
 
-        let results = exec_results.lock().unwrap();
-        let mut windows : Vec<Vec<Option<CSGWindow>>> = CSGScreen::init_windows(height, width);
-        let mut x : usize = 0;
-        let mut y : usize = 0;
+    /// Lines taken up by the pinned column names
+    fn header_rows(&self) -> usize {
+        match self.header {
+            Some(_) => 1,
+            None => 0
+        }
+    }
 
-        // Column names
-        for name in results.get_col_names().unwrap().iter() {
-            let mut window = CSGWindow::new(sqlite.clone(), false, 1, CELL_WIDTH, y, x * CELL_WIDTH);
-            window.set_text(name.clone());
-            windows[x][y] = Some(window);
-            x = x + 1;
+    /// Number of rows that fit between the header and the status line
+    fn visible_rows(&self) -> usize {
+        let reserved = self.header_rows() + 1;
+        if self.height > reserved { self.height - reserved } else { 1 }
+    }
+
+    /// Number of columns that fit across the screen
+    fn visible_cols(&self) -> usize {
+        self.cols_from(self.offset.0)
+    }
+
+    /// Number of columns that fit across the screen when column `x` is the
+    /// leftmost one, always at least one. Blank columns fill the room past
+    /// the end of the data.
+    fn cols_from(&self, x : usize) -> usize {
+        let mut used = 0;
+        let mut count = 0;
+        loop {
+            let width = self.col_width(x + count);
+            if count > 0 && used + width > self.width {
+                break;
+            }
+            used += width + COLUMN_GAP;
+            count += 1;
         }
-        x = 0;
-        y = y + 1;
+        count
+    }
+
+    /// Width of column `x`
+    fn col_width(&self, x : usize) -> usize {
+        self.col_widths.get(x).map(|width| *width).unwrap_or(self.max_width)
+    }
 
-        // Rows
-        for row in results.col_text.iter() {
-            for text in row.iter() {
-                let mut window = CSGWindow::new(sqlite.clone(), true, 1, CELL_WIDTH, y, x * CELL_WIDTH);
-                window.set_text(text.clone());
-                if x == 0  && y == 1 {
-                    window.select()
+    /// Sizes each column to fit its name and the values in the first rows
+    /// loaded, no narrower than MIN_CELL_WIDTH and no wider than `max_width`,
+    /// then lays the screen out again
+    fn fit_columns(&mut self) {
+        let max_width = cmp::max(self.max_width, MIN_CELL_WIDTH);
+        let sampled = cmp::min(self.num_rows(), SAMPLE_ROWS);
+        let mut widths : Vec<usize> = Vec::new();
+        for x in (0..self.num_cols()) {
+            let mut width = match self.header {
+                Some(ref names) => names.get(x).map(|name| text_width(name.as_slice())).unwrap_or(0),
+                None => 0
+            };
+            for y in (0..sampled) {
+                if self.row(y).is_some() {
+                    width = cmp::max(width, text_width(self.cell_text(x, y).as_slice()));
                 }
-                windows[x][y] = Some(window);
-                x = x + 1;
             }
-            x = 0;
-            y = y + 1;
+            widths.push(cmp::min(cmp::max(width, MIN_CELL_WIDTH), max_width));
         }
-
-        Ok(CSGScreen {
-            sqlite : sqlite,
-            rows : height,
-            cols : width,
-            windows : windows,
-            active_window : (0, 1),
-            kind : ScreenKind::TableDump
-        })
+        self.col_widths = widths;
+        self.layout();
+        self.scroll_to_active();
     }
 
-    // Factory constructor for table list screens
-    fn new_table_list(sqlite : Sqlite, width : usize, height : usize) -> Result<CSGScreen, String> {
-        match sqlite.list_tables() {
+    /// Widens column `x` by RESIZE_STEP, up to the width of the screen, or
+    /// narrows it down to MIN_CELL_WIDTH, and redraws
+    fn resize_column(&mut self, x : usize, wider : bool) -> Result<(), SqliteError> {
+        let width = match self.col_widths.get(x) {
+            Some(width) => { *width },
+            None => { return Ok(()); }
+        };
+        self.col_widths[x] = match wider {
+            true => cmp::max(cmp::min(width + RESIZE_STEP, self.width), width),
+            false => cmp::max(if width > RESIZE_STEP { width - RESIZE_STEP } else { 0 }, MIN_CELL_WIDTH)
+        };
+        self.layout();
+        self.scroll_to_active();
+        match self.load_visible() {
             Ok(_) => { },
-            Err(msg) => { return Err(msg); }
+            Err(err) => { return Err(err); }
         };
+        self.write_all();
+        Ok(())
+    }
 
-        let results = exec_results.lock().unwrap();
-        let rows = height;
-        let cols = width / CELL_WIDTH;
-        let mut windows : Vec<Vec<Option<CSGWindow>>> = CSGScreen::init_windows(rows, cols);
-        let mut y : usize = 0;
+    /// Number of rows of data
+    fn num_rows(&self) -> usize {
+        match self.source {
+            RowSource::Rows(ref rows) => rows.len(),
+            RowSource::Table(ref pager) => pager.num_rows()
+        }
+    }
 
-        for row in results.col_text.iter() {
-            for text in row.iter() {
-                let mut window = CSGWindow::new(sqlite.clone(), true, 1, CELL_WIDTH, y, 0);
-                window.set_text(text.clone());
-                if y == 0 {
-                    window.select();
-                }
-                windows[0][y] = Some(window);
-                y = y + 1;
+    /// Number of columns of data
+    fn num_cols(&self) -> usize {
+        match self.header {
+            Some(ref names) => names.len(),
+            None => self.row(0).map(|row| row.len()).unwrap_or(0)
+        }
+    }
+
+    /// Row of data at `y`, if it is loaded
+    fn row(&self, y : usize) -> Option<&Vec<Value>> {
+        match self.source {
+            RowSource::Rows(ref rows) => rows.get(y),
+            RowSource::Table(ref pager) => pager.get(y)
+        }
+    }
+
+    /// Makes sure the rows in the viewport are loaded
+    fn load_visible(&mut self) -> Result<(), SqliteError> {
+        let start = self.offset.1;
+        let count = self.visible_rows();
+        if let ScreenKind::TableList = self.kind {
+            self.count_rows(start, count);
+        }
+        match self.source {
+            RowSource::Rows(_) => { Ok(()) },
+            RowSource::Table(ref mut pager) => { pager.fetch(start, count) }
+        }
+    }
+
+    /// Fills in the row counts of the tables listed from `start` to
+    /// `start + count` that haven't been counted yet
+    fn count_rows(&mut self, start : usize, count : usize) {
+        let end = cmp::min(start + count, self.objects.len());
+        for y in (start..end) {
+            let uncounted = match self.source {
+                RowSource::Rows(ref rows) => { rows[y][2] == Value::Null },
+                RowSource::Table(_) => { false }
+            };
+            if !uncounted {
+                continue;
+            }
+            // Tables of virtual table modules that aren't loaded can't be read
+            let rows = match self.sqlite.count_rows(self.objects[y].name.as_slice()) {
+                Ok(n) => { Value::Integer(n as i64) },
+                Err(_) => { Value::Text("?".to_string()) }
+            };
+            if let RowSource::Rows(ref mut cells) = self.source {
+                cells[y][2] = rows;
             }
         }
+    }
+
+    /// Whether there is data at (x, y)
+    fn has_cell(&self, x : usize, y : usize) -> bool {
+        x < self.num_cols() && y < self.num_rows()
+    }
+
+    /// Text shown for the cell at (x, y), blank past the end of the data
+    fn cell_text(&self, x : usize, y : usize) -> String {
+        match self.row(y).and_then(|row| row.get(x)) {
+            Some(&Value::Null) => self.theme.null_text.clone(),
+            Some(value) => value.to_text(),
+            None => "".to_string()
+        }
+    }
 
-        Ok(CSGScreen {
-            sqlite : sqlite,
-            rows : rows,
-            cols : cols,
-            windows : windows,
-            active_window : (0, 0),
-            kind : ScreenKind::TableList
-        })
+    /// Attributes for the cell at (x, y)
+    fn cell_attr(&self, x : usize, y : usize) -> Attr {
+        self.row(y)
+            .and_then(|row| row.get(x))
+            .map(|value| self.theme.value(value))
+            .unwrap_or(A_NORMAL)
+    }
+
+    /// Creates the windows making up the viewport. Each takes in the gap
+    /// after its column and the last reaches the edge of the screen, so
+    /// nothing is left over from a layout with other widths.
+    fn layout(&mut self) {
+        let header_rows = self.header_rows();
+        let visible_rows = self.visible_rows();
+        let visible_cols = self.visible_cols();
+
+        let mut header_windows : Vec<CSGWindow> = Vec::new();
+        let mut windows : Vec<Vec<CSGWindow>> = Vec::new();
+        let mut begin_x = 0;
+        for i in (0..visible_cols) {
+            let ncols = match i + 1 == visible_cols {
+                true => self.width - begin_x,
+                false => self.col_width(self.offset.0 + i) + COLUMN_GAP
+            };
+            if header_rows > 0 {
+                header_windows.push(CSGWindow::new(self.sqlite.clone(), false, 1, ncols, 0, begin_x));
+            }
+            let mut column : Vec<CSGWindow> = Vec::new();
+            for j in (0..visible_rows) {
+                column.push(CSGWindow::new(self.sqlite.clone(), true, 1, ncols, header_rows + j, begin_x));
+            }
+            windows.push(column);
+            begin_x += ncols;
+        }
+
+        self.header_windows = header_windows;
+        self.windows = windows;
+        self.status_window = CSGWindow::new(self.sqlite.clone(), false, 1, self.width, self.height - 1, 0);
+    }
+
+    /// Lays the screen out again for a terminal of a new size, keeping the
+    /// selection in view. Nothing is drawn.
+    fn resize(&mut self, width : usize, height : usize) -> Result<(), SqliteError> {
+        // Hex dumps keep the same bytes in view, even with more on a line
+        let line = match self.viewed {
+            Some(Value::Blob(_)) => self.active_window.1 * hex_per_line(self.width) / hex_per_line(width),
+            _ => self.active_window.1
+        };
+        self.width = width;
+        self.height = height;
+        if let ScreenKind::DatabaseList = self.kind {
+            self.max_width = width;
+            self.fit_columns();
+        }
+        // Wrap the value again, keeping the selection on a line
+        let rewrapped = self.viewed.as_ref().map(|value| view_rows(value, width).0);
+        if let Some(rows) = rewrapped {
+            let last = rows.len() - 1;
+            self.source = RowSource::Rows(rows);
+            self.active_window.1 = cmp::min(line, last);
+            self.max_width = width;
+            self.fit_columns();
+        }
+        self.layout();
+        self.scroll_to_active();
+        self.load_visible()
+    }
+
+    /// Moves the viewport so the selection is visible, returns true if it moved
+    fn scroll_to_active(&mut self) -> bool {
+        let (x, y) = self.active_window;
+        let (mut ox, mut oy) = self.offset;
+        let visible_cols = self.visible_cols();
+        let visible_rows = self.visible_rows();
+        if x < ox {
+            ox = x;
+        } else if x >= ox + visible_cols {
+            // Columns differ in width, so step until the selection fits
+            while x >= ox + self.cols_from(ox) {
+                ox += 1;
+            }
+        }
+        if y < oy {
+            oy = y;
+        } else if y >= oy + visible_rows {
+            oy = y + 1 - visible_rows;
+        }
+        let moved = (ox, oy) != self.offset;
+        let relayout = ox != self.offset.0;
+        self.offset = (ox, oy);
+        // Other columns are in view, which may fit a different number of them
+        if relayout {
+            self.layout();
+        }
+        moved
     }
 
     /// Get method for active window
     fn get_active_window(&self) -> Option<&CSGWindow> {
-        self.windows[self.active_window.0][self.active_window.1].as_ref()
+        let (x, y) = self.active_window;
+        if !self.has_cell(x, y) || x < self.offset.0 || y < self.offset.1 {
+            return None;
+        }
+        self.windows.get(x - self.offset.0).and_then(|column| column.get(y - self.offset.1))
     }
 
-    /// Set method for active window
-    fn set_active_window(&mut self, prev : (i64, i64), next : (i64, i64)) {
-        let (x, y) : (i64, i64) = match self.get_window(next.0, next.1) {
-            Some(w) => {
-                match w.selectable {
-                    true => { 
-                        w.select();
-                        next
-                    },
-                    false => { prev }
-                }
-            },
-            None => { prev }
-        };
-        if x != prev.0 || y != prev.1 {
-            let prev_w = self.get_window(prev.0, prev.1).unwrap();
-            prev_w.unselect();
+    /// Set method for active window, moves are ignored if there is no data
+    /// at `next`
+    fn set_active_window(&mut self, prev : (i64, i64), next : (i64, i64)) -> Result<(), SqliteError> {
+        if next.0 < 0 || next.1 < 0 || !self.has_cell(next.0 as usize, next.1 as usize) {
+            return Ok(());
+        }
+        self.active_window = (next.0 as usize, next.1 as usize);
+        if self.scroll_to_active() {
+            match self.load_visible() {
+                Ok(_) => { },
+                Err(err) => { return Err(err); }
+            };
+            self.write_all();
+            return Ok(());
+        }
+        match self.get_window(prev.0, prev.1) {
+            Some(w) => { w.unselect(); },
+            None => { }
+        }
+        let highlight = self.theme.selected;
+        match self.get_window(next.0, next.1) {
+            Some(w) => { w.select(highlight); },
+            None => { }
         }
-        self.active_window = (x as usize, y as usize);
+        self.write_status();
+        Ok(())
     }
 
-    /// Get method for a pointer to a window
+    /// Get method for a pointer to the window showing data at (x, y)
     fn get_window(&mut self, x : i64, y : i64) -> Option<&mut CSGWindow> {
-        if x < 0 || x >= self.rows as i64 || y < 0 || y >= self.cols as i64 {
+        if x < self.offset.0 as i64 || y < self.offset.1 as i64 {
             return None
         }
-        self.windows[x as usize][y as usize].as_mut()
+        let i = x as usize - self.offset.0;
+        let j = y as usize - self.offset.1;
+        if i >= self.windows.len() || j >= self.windows[i].len() {
+            return None
+        }
+        Some(&mut self.windows[i][j])
+    }
+
+    /// Reads a keystroke, the status line always exists even when there is
+    /// no data to select
+    fn read_in(&self) -> usize {
+        self.status_window.window.read_in()
     }
 
     // Clears all window text in this screen
     fn clear_all(&self) {
-        for i in (0..self.rows) {
-            for j in (0..self.cols) {
-                match self.windows[i][j] {
-                    Some(ref w) => { w.window.clear(); },
-                    None => { continue; }
-                }
+        for w in self.header_windows.iter() {
+            w.window.clear();
+        }
+        for column in self.windows.iter() {
+            for w in column.iter() {
+                w.window.clear();
             }
         }
+        self.status_window.window.clear();
     }
 
     // Draws all window text in this screen
-    fn write_all(&self) {
-        for i in (0..self.rows) {
-            for j in (0..self.cols) {
-                match self.windows[i][j] {
-                    Some(ref w) => { 
-                    	w.window.write(w.text.as_slice()); 
-                    	if (i, j) == self.active_window {
-                    		w.select();
-                    	}
+    fn write_all(&mut self) {
+        for i in (0..self.header_windows.len()) {
+            let text = match self.header {
+                Some(ref names) => names.get(self.offset.0 + i).map(|n| n.clone()).unwrap_or("".to_string()),
+                None => "".to_string()
+            };
+            let width = self.col_width(self.offset.0 + i);
+            self.header_windows[i].set_styled_text(truncate(text, width), self.theme.header);
+        }
+        for i in (0..self.windows.len()) {
+            for j in (0..self.windows[i].len()) {
+                let (x, y) = (self.offset.0 + i, self.offset.1 + j);
+                let text = truncate(self.cell_text(x, y), self.col_width(x));
+                let attr = self.cell_attr(x, y);
+                self.windows[i][j].set_styled_text(text, attr);
+                if (x, y) == self.active_window && self.has_cell(x, y) {
+                    self.windows[i][j].select(self.theme.selected);
+                }
+            }
+        }
+        self.write_status();
+    }
+
+    /// Stores `value` in the cell at (x, y) and redraws
+    fn update_cell(&mut self, x : usize, y : usize, value : Value) -> Result<(), SqliteError> {
+        let result = match self.source {
+            RowSource::Table(ref mut pager) => { pager.update(y, x, &value) },
+            RowSource::Rows(ref mut rows) => {
+                match rows.get_mut(y).and_then(|row| row.get_mut(x)) {
+                    Some(cell) => {
+                        *cell = value;
+                        Ok(())
                     },
-                    None => { continue; }
+                    None => { Ok(()) }
                 }
             }
+        };
+        match result.and_then(|_| self.reload_rows()) {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+        self.write_all();
+        Ok(())
+    }
+
+    /// Adds a row to the table, it is drawn once this screen is shown again
+    fn insert_row(&mut self, columns : &[String], values : &[Value]) -> Result<(), SqliteError> {
+        let result = match self.source {
+            RowSource::Table(ref mut pager) => { pager.insert(columns, values) },
+            RowSource::Rows(_) => {
+                Err(SqliteError::misuse("rows can only be added to tables".to_string(), None))
+            }
+        };
+        result.and_then(|_| self.reload_rows())
+    }
+
+    /// Deletes the row at `y` and redraws
+    fn delete_row(&mut self, y : usize) -> Result<(), SqliteError> {
+        let result = match self.source {
+            RowSource::Table(ref mut pager) => { pager.delete(y) },
+            RowSource::Rows(_) => {
+                Err(SqliteError::misuse("rows can only be deleted from tables".to_string(), None))
+            }
+        };
+        match result.and_then(|_| self.reload_rows()) {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+        self.write_all();
+        Ok(())
+    }
+
+    /// Loads the rows in view after the data changed, moving the selection
+    /// back onto the data if its row went away
+    fn reload_rows(&mut self) -> Result<(), SqliteError> {
+        let (x, y) = self.active_window;
+        if y >= self.num_rows() {
+            let last = if self.num_rows() > 0 { self.num_rows() - 1 } else { 0 };
+            self.active_window = (x, last);
+        }
+        self.scroll_to_active();
+        self.load_visible()
+    }
+
+    /// Opens a prompt for changing the value at (x, y), named `name`. Returns
+    /// None and says why when it can't be edited.
+    fn edit_prompt(&mut self, x : usize, y : usize, name : String) -> Option<Prompt> {
+        let value = match self.row(y).and_then(|row| row.get(x)) {
+            Some(value) => { value.clone() },
+            None => { return None; }
+        };
+        if let Value::Blob(_) = value {
+            self.show_message("BLOBs can't be edited as text".to_string());
+            return None;
         }
+        let prompt = Prompt {
+            label : format!("{} = ", name),
+            editor : LineEditor::new(edit_text(&value).as_slice()),
+            action : PromptAction::EditCell(x, y, value)
+        };
+        set_cursor_visible(true);
+        self.show_prompt(&prompt);
+        Some(prompt)
+    }
+
+    // Shows a message on the status line until the next move
+    fn show_message(&mut self, text : String) {
+        self.status_window.set_text(text);
+    }
+
+    // Shows a prompt on the status line with the cursor in the text
+    fn show_prompt(&mut self, prompt : &Prompt) {
+        let text = prompt.editor.text();
+        let label_len = prompt.label.chars().count();
+        let room = if self.width > label_len + 1 { self.width - label_len - 1 } else { 1 };
+        // Scroll long lines so the cursor stays in view
+        let skip = if prompt.editor.cursor() > room { prompt.editor.cursor() - room } else { 0 };
+        let shown : String = text.chars().skip(skip).take(room).collect();
+        self.status_window.set_text(format!("{}{}", prompt.label, shown));
+        self.status_window.window.move_cursor(0, label_len + prompt.editor.cursor() - skip);
+    }
+
+    /// Writes the rows of the screen to the file at `path` as CSV, led by
+    /// the column names, and returns how many were written. Tables are read
+    /// a row at a time rather than through the cache.
+    fn export_csv(&self, path : &str, delimiter : char) -> Result<usize, String> {
+        let file = match File::create(&Path::new(path)) {
+            Ok(file) => { file },
+            Err(err) => { return Err(format!("{}: {}", path, err)); }
+        };
+        let mut csv = CsvWriter::new(BufferedWriter::new(file), delimiter);
+        let mut count = 0;
+        match self.source {
+            RowSource::Rows(ref rows) => {
+                let names = match self.header {
+                    Some(ref names) => { names.clone() },
+                    None => { Vec::new() }
+                };
+                match csv.write_header(names.as_slice()) {
+                    Ok(_) => { },
+                    Err(err) => { return Err(format!("{}: {}", path, err)); }
+                }
+                for row in rows.iter() {
+                    match csv.write_row(row.as_slice()) {
+                        Ok(_) => { count += 1; },
+                        Err(err) => { return Err(format!("{}: {}", path, err)); }
+                    }
+                }
+            },
+            RowSource::Table(ref pager) => {
+                let mut stmt = match pager.select_all() {
+                    Ok(stmt) => { stmt },
+                    Err(err) => { return Err(format!("{}: {}", path, err)); }
+                };
+                match csv.write_header(stmt.col_names().as_slice()) {
+                    Ok(_) => { },
+                    Err(err) => { return Err(format!("{}: {}", path, err)); }
+                }
+                let num_cols = stmt.num_cols();
+                loop {
+                    match stmt.step() {
+                        Ok(true) => { },
+                        Ok(false) => { break; },
+                        Err(err) => { return Err(format!("{}: {}", path, err)); }
+                    }
+                    let row : Vec<Value> = (0..num_cols).map(|i| stmt.column_value(i)).collect();
+                    match csv.write_row(row.as_slice()) {
+                        Ok(_) => { count += 1; },
+                        Err(err) => { return Err(format!("{}: {}", path, err)); }
+                    }
+                }
+            }
+        }
+        match csv.finish() {
+            Ok(_) => { Ok(count) },
+            Err(err) => { Err(format!("{}: {}", path, err)) }
+        }
+    }
+
+    /// Exports the rows to `path` and says how it went on the status line
+    fn export(&mut self, path : &str, delimiter : char) {
+        match self.export_csv(path, delimiter) {
+            Ok(count) => { self.show_message(format!("{} rows written to {}", count, path)); },
+            Err(err) => { self.show_message(err); }
+        }
+    }
+
+    /// Moves the selection of a hex dump to the line holding byte `offset`,
+    /// or the last line when it is past the end
+    fn go_to_offset(&mut self, offset : usize) -> Result<(), SqliteError> {
+        let prev = (self.active_window.0 as i64, self.active_window.1 as i64);
+        let line = cmp::min(offset / hex_per_line(self.width), self.num_rows() - 1);
+        self.set_active_window(prev, (0, line as i64))
+    }
+
+    // Shows the title and position of the selection on the status line,
+    // which for a hex dump is the offset of the selected line
+    fn write_status(&mut self) {
+        let (x, y) = self.active_window;
+        let text = match self.viewed {
+            Some(Value::Blob(ref bytes)) => {
+                format!("{}  offset {:08x}/{:08x}", self.title, y * hex_per_line(self.width), bytes.len())
+            },
+            _ => {
+                match self.num_rows() {
+                    0 => format!("{}  (empty)", self.title),
+                    rows => format!("{}  row {}/{}  col {}/{}", self.title, y + 1, rows, x + 1, self.num_cols())
+                }
+            }
+        };
+        self.status_window.set_text(text);
     }
 }
 
+/// Size of the terminal as (columns, lines). When curses can't tell, as when
+/// there is no terminal, $COLUMNS and $LINES are tried before the defaults.
+/// Never less than one column by two lines, room for a cell and the status
+/// line.
+fn terminal_size(curses : &Curses) -> (usize, usize) {
+    let (width, height) = match curses.size() {
+        (width, height) if width > 0 && height > 1 => (width, height),
+        _ => {
+            (get_env_as::<usize>("COLUMNS").unwrap_or(DEFAULT_WIDTH),
+             get_env_as::<usize>("LINES").unwrap_or(DEFAULT_HEIGHT))
+        }
+    };
+    (cmp::max(width, 1), cmp::max(height, 2))
+}
+
 /// Encapsulating structure for the user interface
 pub struct CSG {
-    sqlite : Sqlite,
+    /// Open databases, in the order they were given
+    databases : Vec<Sqlite>,
     curses : Curses,
     width : usize,
     height : usize,
+    theme : Theme,
     pub screens : Vec<CSGScreen>,
-    pub active_screen : usize
+    pub active_screen : usize,
+    /// Line being typed, keys go here instead of moving the selection
+    prompt : Option<Prompt>,
+    /// Statements run in the SQL console, oldest first
+    history : Vec<String>,
+    /// Whether table lists include objects SQLite made for itself
+    show_internal : bool,
+    /// What the keys do
+    keymap : Keymap,
+    /// Statements the console remembers
+    history_size : usize,
+    /// Separates the fields of exported CSV
+    csv_delimiter : char
 }
 
 impl CSG {
-	pub fn new(filename : &str) -> Result<CSG, String> {
-        let sqlite = Sqlite::new(filename);
+	pub fn new(filename : &str, mode : OpenMode) -> Result<CSG, SqliteError> {
+        CSG::new_multi(vec![filename.to_string()].as_slice(), mode)
+	}
+
+    /// Opens several databases, starting on a list of them when there is
+    /// more than one
+    pub fn new_multi(filenames : &[String], mode : OpenMode) -> Result<CSG, SqliteError> {
+        let mut config = Config::new();
+        config.mode = mode;
+        CSG::new_with_config(filenames, config)
+    }
+
+    /// Opens several databases with the settings in `config`
+    pub fn new_with_config(filenames : &[String], config : Config) -> Result<CSG, SqliteError> {
+        let mut databases : Vec<Sqlite> = Vec::new();
+        for filename in filenames.iter() {
+            match Sqlite::new_with_mode(filename.as_slice(), config.mode) {
+                Ok(sqlite) => { databases.push(sqlite); },
+                Err(err) => { return Err(err) }
+            };
+        }
         let curses = Curses::new();
-        let width = match get_env_as::<usize>("COLUMNS") {
-            Some(n) => n,
-            None => DEFAULT_WIDTH
-        };
-        let height = match get_env_as::<usize>("LINES") {
-            Some(n) => n,
-            None => DEFAULT_HEIGHT
-        };
+        let (width, height) = terminal_size(&curses);
+        let theme = Theme::new(&config, &curses);
         let mut screens : Vec<CSGScreen> = Vec::new();
-        match CSGScreen::new_table_list(sqlite.clone(), width, height) {
-            Ok(main_screen) => {
-                screens.push(main_screen);
-
-                return Ok(CSG {
-                    sqlite : sqlite,
-                    curses : curses,
-                    width : width,
-                    height : height,
-                    screens : screens,
-                    active_screen : 0,
-                });
-            },
-            Err(msg) => { return Err(msg) }
+        if databases.len() > 1 {
+            match CSGScreen::new_database_list(databases[0].clone(), width, height, &theme, filenames) {
+                Ok(main_screen) => { screens.push(main_screen); },
+                Err(err) => { return Err(err) }
+            };
+        } else {
+            match CSGScreen::new_table_list(databases[0].clone(), width, height, &theme, false) {
+                Ok(main_screen) => { screens.push(main_screen); },
+                Err(err) => { return Err(err) }
+            };
+        }
+
+        Ok(CSG {
+            databases : databases,
+            curses : curses,
+            width : width,
+            height : height,
+            theme : theme,
+            screens : screens,
+            active_screen : 0,
+            prompt : None,
+            history : Vec::new(),
+            show_internal : false,
+            keymap : config.keymap,
+            history_size : config.history_size,
+            csv_delimiter : config.csv_delimiter
+        })
+    }
+
+    /// Tears down the interface and closes the databases, screens and
+    /// windows hold the other references to the connections
+    pub fn close(self) -> Result<(), SqliteError> {
+        let CSG { databases, curses, screens, .. } = self;
+        drop(screens);
+        drop(curses);
+        let mut result = Ok(());
+        for sqlite in databases.into_iter() {
+            match sqlite.close() {
+                Ok(_) => { },
+                Err(err) => { result = Err(err); }
+            }
+        }
+        result
+    }
+
+    /// Opens a dump of `table` from the current table list
+    pub fn open_table(&mut self, table : &str) -> Result<(), SqliteError> {
+        let object = {
+            let screen = &self.screens[self.active_screen];
+            // Unlisted names such as sqlite_master are still worth a try
+            match screen.objects.iter().find(|o| o.name.as_slice() == table) {
+                Some(object) => { object.clone() },
+                None => {
+                    SchemaObject { name : table.to_string(), kind : ObjectKind::Table, table : table.to_string() }
+                }
+            }
         };
-	}
+        let sqlite = self.screens[self.active_screen].sqlite.clone();
+        match CSGScreen::new_table_dump(sqlite, self.width, self.height, &self.theme, object) {
+            Ok(screen) => {
+                self.screens[self.active_screen].clear_all();
+                self.add_screen(screen);
+                Ok(())
+            },
+            Err(err) => { Err(err) }
+        }
+    }
 
+    /// Runs `sql` against the current database and shows its results
+    pub fn run_query(&mut self, sql : &str) -> Result<(), SqliteError> {
+        let sqlite = self.screens[self.active_screen].sqlite.clone();
+        match CSGScreen::new_query_result(sqlite, self.width, self.height, &self.theme, sql) {
+            Ok(screen) => {
+                self.screens[self.active_screen].clear_all();
+                self.add_screen(screen);
+                Ok(())
+            },
+            Err(err) => { Err(err) }
+        }
+    }
 
     /// Main loop, handles keystrokes & dispatches events
-    pub fn run_forever(&mut self) -> Result<(), String> {
+    pub fn run_forever(&mut self) -> Result<(), SqliteError> {
         loop {
             let c = self.read_current_window();
             match self.dispatch_key(c) {
             	Some(r) => { 
                     match r {
                         Ok(_) => { },
-                        Err(msg) => { return Err(msg); }
+                        Err(err) => { return Err(err); }
                     }
                 },
             	None => { return Ok(()); }
@@ -296,63 +1382,168 @@ impl CSG {
         }
     }
 
-    pub fn dispatch_key(&mut self, c : usize) -> Option<Result<(), String>> {
-        match c {
-            KEY_q => { 
-            	return self.handle_quit();
-            },
-            KEY_h => {
-            	return self.handle_left();
-            },
-            KEY_j => {
-                return self.handle_down();
-            },
-            KEY_k => {
-                return self.handle_up();
-            },
-            KEY_l => {
-                return self.handle_right();
-            },
-            KEY_e => {
-                return self.handle_edit();
-            },
-            _ => { return Some(Ok(())); }
+    /// Handles a key code as read from curses
+    pub fn dispatch_key(&mut self, c : usize) -> Option<Result<(), SqliteError>> {
+        self.dispatch(Key::from_code(c))
+    }
+
+    /// Handles a keystroke, returns None once the last screen is closed
+    pub fn dispatch(&mut self, key : Key) -> Option<Result<(), SqliteError>> {
+        if key == Key::Resize {
+            return self.handle_resize();
+        }
+        if self.prompt.is_some() {
+            return self.handle_prompt_key(key);
+        }
+        match self.keymap.feed(key) {
+            Some(action) => { self.perform(action) },
+            // Unbound, or partway through a sequence
+            None => { Some(Ok(())) }
+        }
+    }
+
+    /// Carries out what a key was bound to
+    pub fn perform(&mut self, action : Action) -> Option<Result<(), SqliteError>> {
+        match action {
+            Action::Back => { self.handle_quit() },
+            Action::MoveLeft => { self.handle_left() },
+            Action::MoveDown => { self.handle_down() },
+            Action::MoveUp => { self.handle_up() },
+            Action::MoveRight => { self.handle_right() },
+            Action::PageUp => { self.handle_page_up() },
+            Action::PageDown => { self.handle_page_down() },
+            Action::FirstRow => { self.handle_first_row() },
+            Action::LastRow => { self.handle_last_row() },
+            Action::FirstColumn => { self.handle_first_col() },
+            Action::LastColumn => { self.handle_last_col() },
+            // Both open the selection on lists and edit it on a table dump
+            Action::DrillIn | Action::Edit => { self.handle_edit() },
+            Action::InsertRow => { self.handle_insert() },
+            Action::DeleteRow => { self.handle_delete() },
+            Action::WriteRow => { self.handle_write() },
+            Action::Console => { self.handle_console() },
+            Action::Schema => { self.handle_schema() },
+            Action::ToggleInternal => { self.handle_toggle_internal() },
+            Action::WidenColumn => { self.handle_resize_column(true) },
+            Action::NarrowColumn => { self.handle_resize_column(false) },
+            Action::View => { self.handle_view() },
+            Action::GoToOffset => { self.handle_go_to_offset() },
+            Action::Export => { self.handle_export() }
         }
     }
 
-    pub fn handle_left(&mut self) -> Option<Result<(), String>> {
+    /// Replaces the key bindings
+    pub fn set_keymap(&mut self, keymap : Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn handle_left(&mut self) -> Option<Result<(), SqliteError>> {
     	let prev = self.get_active_window_coords();
         let (mut x, y) = prev;
         x = x - 1;
-        self.set_active_window(prev, (x, y));
-        Some(Ok(()))
+        Some(self.set_active_window(prev, (x, y)))
     }
 
-    pub fn handle_down(&mut self) -> Option<Result<(), String>> {
+    pub fn handle_down(&mut self) -> Option<Result<(), SqliteError>> {
     	let prev = self.get_active_window_coords();
         let (x, mut y) = prev;
         y = y + 1;
-        self.set_active_window(prev, (x, y));
-        Some(Ok(()))
+        Some(self.set_active_window(prev, (x, y)))
     }
 
-    pub fn handle_up(&mut self) -> Option<Result<(), String>> {
+    pub fn handle_up(&mut self) -> Option<Result<(), SqliteError>> {
     	let prev = self.get_active_window_coords();
         let (x, mut y) = prev;
         y = y - 1;
-        self.set_active_window(prev, (x, y));
-        Some(Ok(()))
+        Some(self.set_active_window(prev, (x, y)))
     }
 
-    pub fn handle_right(&mut self) -> Option<Result<(), String>> {
+    pub fn handle_right(&mut self) -> Option<Result<(), SqliteError>> {
     	let prev = self.get_active_window_coords();
         let (mut x, y) = prev;
         x = x + 1;
-        self.set_active_window(prev, (x, y));
-        Some(Ok(()))
+        Some(self.set_active_window(prev, (x, y)))
+    }
+
+    /// Moves the selection up a screenful
+    pub fn handle_page_up(&mut self) -> Option<Result<(), SqliteError>> {
+        let prev = self.get_active_window_coords();
+        let page = self.screens[self.active_screen].visible_rows() as i64;
+        Some(self.set_active_window(prev, (prev.0, cmp::max(prev.1 - page, 0))))
+    }
+
+    /// Moves the selection down a screenful
+    pub fn handle_page_down(&mut self) -> Option<Result<(), SqliteError>> {
+        let prev = self.get_active_window_coords();
+        let page = self.screens[self.active_screen].visible_rows() as i64;
+        let last = self.screens[self.active_screen].num_rows() as i64 - 1;
+        Some(self.set_active_window(prev, (prev.0, cmp::min(prev.1 + page, last))))
+    }
+
+    /// Moves the selection to the first row
+    pub fn handle_first_row(&mut self) -> Option<Result<(), SqliteError>> {
+        let prev = self.get_active_window_coords();
+        Some(self.set_active_window(prev, (prev.0, 0)))
+    }
+
+    /// Moves the selection to the last row
+    pub fn handle_last_row(&mut self) -> Option<Result<(), SqliteError>> {
+        let prev = self.get_active_window_coords();
+        let last = self.screens[self.active_screen].num_rows() as i64 - 1;
+        Some(self.set_active_window(prev, (prev.0, last)))
+    }
+
+    /// Moves the selection to the first column
+    pub fn handle_first_col(&mut self) -> Option<Result<(), SqliteError>> {
+        let prev = self.get_active_window_coords();
+        Some(self.set_active_window(prev, (0, prev.1)))
+    }
+
+    /// Moves the selection to the last column
+    pub fn handle_last_col(&mut self) -> Option<Result<(), SqliteError>> {
+        let prev = self.get_active_window_coords();
+        let last = self.screens[self.active_screen].num_cols() as i64 - 1;
+        Some(self.set_active_window(prev, (last, prev.1)))
+    }
+
+    /// Widens or narrows the selected column
+    pub fn handle_resize_column(&mut self, wider : bool) -> Option<Result<(), SqliteError>> {
+        let screen = &mut self.screens[self.active_screen];
+        let x = screen.active_window.0;
+        Some(screen.resize_column(x, wider))
+    }
+
+    /// Fits the screens to the terminal after it changed size
+    pub fn handle_resize(&mut self) -> Option<Result<(), SqliteError>> {
+        let (width, height) = terminal_size(&self.curses);
+        Some(self.resize(width, height))
+    }
+
+    /// Lays every screen out for a terminal of `width` columns and `height`
+    /// lines and redraws the active one
+    pub fn resize(&mut self, width : usize, height : usize) -> Result<(), SqliteError> {
+        // Room for at least one cell above the status line
+        let width = cmp::max(width, 1);
+        let height = cmp::max(height, 2);
+        self.width = width;
+        self.height = height;
+        for screen in self.screens.iter_mut() {
+            match screen.resize(width, height) {
+                Ok(_) => { },
+                Err(err) => { return Err(err); }
+            }
+        }
+        self.curses.clear();
+        let screen = &mut self.screens[self.active_screen];
+        screen.write_all();
+        match self.prompt {
+            Some(ref prompt) => { screen.show_prompt(prompt); },
+            None => { }
+        }
+        Ok(())
     }
 
-    pub fn handle_quit(&mut self) -> Option<Result<(), String>> {
+    pub fn handle_quit(&mut self) -> Option<Result<(), SqliteError>> {
         self.screens[self.active_screen].clear_all();
         self.screens.pop();
         if self.screens.len() == 0 {
@@ -364,30 +1555,466 @@ impl CSG {
     }
 
     // Dispatches an edit depending on the kind of screen we are on
-    fn handle_edit(&mut self) -> Option<Result<(), String>> {
+    fn handle_edit(&mut self) -> Option<Result<(), SqliteError>> {
         match self.screens[self.active_screen].kind {
+            ScreenKind::DatabaseList => {
+                self.screens[self.active_screen].clear_all();
+
+                let sqlite = self.databases[self.screens[self.active_screen].active_window.1].clone();
+                match CSGScreen::new_table_list(sqlite, self.width, self.height, &self.theme, self.show_internal) {
+                    Ok(table_list_screen) => {
+                        self.add_screen(table_list_screen);
+                        return Some(Ok(()));
+                    },
+                    Err(err) => { return Some(Err(err)) }
+                }
+            },
             ScreenKind::TableList => {
+                let object = {
+                    let screen = &self.screens[self.active_screen];
+                    match screen.objects.get(screen.active_window.1) {
+                        Some(object) => { object.clone() },
+                        None => { return Some(Ok(())); }
+                    }
+                };
+                match object.kind {
+                    ObjectKind::Index | ObjectKind::Trigger => { return self.handle_schema(); },
+                    _ => { }
+                }
                 self.screens[self.active_screen].clear_all();
 
-                let active_window_text = self.get_active_window().unwrap().text.clone();
-                match CSGScreen::new_table_dump(self.sqlite.clone(),
+                let sqlite = self.screens[self.active_screen].sqlite.clone();
+                match CSGScreen::new_table_dump(sqlite,
                                                 self.width,
                                                 self.height,
-                                                active_window_text) {
+                                                &self.theme,
+                                                object) {
                     Ok(table_dump_screen) => {
                         self.add_screen(table_dump_screen);
                         return Some(Ok(()));
                     },
-                    Err(msg) => { return Some(Err(msg)) }
+                    Err(err) => { return Some(Err(err)) }
                 }
             },
             ScreenKind::TableDump => {
-                // Edit cells here
+                let prompt = {
+                    let screen = &mut self.screens[self.active_screen];
+                    if screen.read_only {
+                        screen.show_message("read-only: cells can't be edited".to_string());
+                        return Some(Ok(()));
+                    }
+                    let (x, y) = screen.active_window;
+                    let name = match screen.header {
+                        Some(ref names) => names[x].clone(),
+                        None => "".to_string()
+                    };
+                    screen.edit_prompt(x, y, name)
+                };
+                self.prompt = prompt;
+                return Some(Ok(()));
+            },
+            ScreenKind::InsertForm => {
+                // Whichever column is selected, the value is what gets edited
+                let prompt = {
+                    let screen = &mut self.screens[self.active_screen];
+                    let y = screen.active_window.1;
+                    let name = screen.cell_text(0, y);
+                    screen.edit_prompt(2, y, name)
+                };
+                self.prompt = prompt;
+                return Some(Ok(()));
+            },
+            ScreenKind::QueryResult => {
                 return Some(Ok(()));
+            },
+            ScreenKind::Console => {
+                self.open_sql_prompt();
+                return Some(Ok(()));
+            },
+            ScreenKind::Schema | ScreenKind::CellView => {
+                return Some(Ok(()));
+            }
+        }
+    }
+
+    /// Opens the whole of the selected value on a screen of its own
+    pub fn handle_view(&mut self) -> Option<Result<(), SqliteError>> {
+        let (sqlite, name, value) = {
+            let screen = &self.screens[self.active_screen];
+            match screen.kind {
+                ScreenKind::TableDump | ScreenKind::QueryResult | ScreenKind::Console => { },
+                _ => { return Some(Ok(())); }
+            }
+            let (x, y) = screen.active_window;
+            let value = match screen.row(y).and_then(|row| row.get(x)) {
+                Some(value) => { value.clone() },
+                None => { return Some(Ok(())); }
+            };
+            let column = match screen.header {
+                Some(ref names) => names.get(x).map(|n| n.clone()).unwrap_or("".to_string()),
+                None => "".to_string()
+            };
+            // Query results are titled with their SQL, too long to repeat
+            let name = match screen.kind {
+                ScreenKind::TableDump => format!("{}.{} row {}", screen.title, column, y + 1),
+                _ => format!("{} row {}", column, y + 1)
+            };
+            (screen.sqlite.clone(), name, value)
+        };
+        match CSGScreen::new_cell_view(sqlite, self.width, self.height, &self.theme, name, value) {
+            Ok(view) => {
+                self.screens[self.active_screen].clear_all();
+                self.add_screen(view);
+                Some(Ok(()))
+            },
+            Err(err) => { Some(Err(err)) }
+        }
+    }
+
+    /// Asks for an offset to move to in a hex dump
+    pub fn handle_go_to_offset(&mut self) -> Option<Result<(), SqliteError>> {
+        let prompt = {
+            let screen = &mut self.screens[self.active_screen];
+            match screen.viewed {
+                Some(Value::Blob(_)) => { },
+                _ => { return Some(Ok(())); }
             }
+            let prompt = Prompt {
+                label : "offset: 0x".to_string(),
+                editor : LineEditor::new(""),
+                action : PromptAction::GoToOffset
+            };
+            set_cursor_visible(true);
+            screen.show_prompt(&prompt);
+            prompt
+        };
+        self.prompt = Some(prompt);
+        Some(Ok(()))
+    }
+
+    /// Asks for a file to write the rows of a table or result to, named after
+    /// the table to begin with
+    pub fn handle_export(&mut self) -> Option<Result<(), SqliteError>> {
+        let prompt = {
+            let screen = &mut self.screens[self.active_screen];
+            let path = match screen.kind {
+                ScreenKind::TableDump => format!("{}.csv", screen.title),
+                ScreenKind::QueryResult | ScreenKind::Console => "result.csv".to_string(),
+                _ => { return Some(Ok(())); }
+            };
+            let prompt = Prompt {
+                label : "export to: ".to_string(),
+                editor : LineEditor::new(path.as_slice()),
+                action : PromptAction::Export
+            };
+            set_cursor_visible(true);
+            screen.show_prompt(&prompt);
+            prompt
+        };
+        self.prompt = Some(prompt);
+        Some(Ok(()))
+    }
+
+    /// Opens a form for a new row of the table being dumped
+    pub fn handle_insert(&mut self) -> Option<Result<(), SqliteError>> {
+        let (sqlite, table) = {
+            let screen = &mut self.screens[self.active_screen];
+            match screen.kind {
+                ScreenKind::TableDump => { },
+                _ => { return Some(Ok(())); }
+            }
+            if screen.read_only {
+                screen.show_message("read-only: rows can't be added".to_string());
+                return Some(Ok(()));
+            }
+            (screen.sqlite.clone(), screen.title.clone())
+        };
+        match CSGScreen::new_insert_form(sqlite, self.width, self.height, &self.theme, table.as_slice()) {
+            Ok(form) => {
+                self.screens[self.active_screen].clear_all();
+                self.add_screen(form);
+                Some(Ok(()))
+            },
+            Err(err) => { Some(Err(err)) }
         }
     }
 
+    /// Asks whether to delete the selected row of the table being dumped
+    pub fn handle_delete(&mut self) -> Option<Result<(), SqliteError>> {
+        let prompt = {
+            let screen = &mut self.screens[self.active_screen];
+            match screen.kind {
+                ScreenKind::TableDump => { },
+                _ => { return Some(Ok(())); }
+            }
+            if screen.read_only {
+                screen.show_message("read-only: rows can't be deleted".to_string());
+                return Some(Ok(()));
+            }
+            let y = screen.active_window.1;
+            if y >= screen.num_rows() {
+                return Some(Ok(()));
+            }
+            let prompt = Prompt {
+                label : format!("delete row {}? (y/n) ", y + 1),
+                editor : LineEditor::new(""),
+                action : PromptAction::DeleteRow(y)
+            };
+            set_cursor_visible(true);
+            screen.show_prompt(&prompt);
+            prompt
+        };
+        self.prompt = Some(prompt);
+        Some(Ok(()))
+    }
+
+    /// Adds the row typed into an insert form to the table beneath it
+    pub fn handle_write(&mut self) -> Option<Result<(), SqliteError>> {
+        let (columns, values) = {
+            let form = &self.screens[self.active_screen];
+            match form.kind {
+                ScreenKind::InsertForm => { },
+                _ => { return Some(Ok(())); }
+            }
+            let rows = match form.source {
+                RowSource::Rows(ref rows) => { rows },
+                RowSource::Table(_) => { return Some(Ok(())); }
+            };
+            let columns : Vec<String> = rows.iter().map(|row| row[0].to_text()).collect();
+            let values : Vec<Value> = rows.iter().map(|row| row[2].clone()).collect();
+            (columns, values)
+        };
+        let result = self.screens[self.active_screen - 1].insert_row(columns.as_slice(), values.as_slice());
+        match result {
+            Ok(_) => { self.handle_quit() },
+            Err(err) => {
+                self.screens[self.active_screen].show_message(format!("{}", err));
+                Some(Ok(()))
+            }
+        }
+    }
+
+    /// Shows or hides the objects SQLite made for itself on a table list
+    pub fn handle_toggle_internal(&mut self) -> Option<Result<(), SqliteError>> {
+        let (sqlite, selected) = {
+            let screen = &self.screens[self.active_screen];
+            match screen.kind {
+                ScreenKind::TableList => { },
+                _ => { return Some(Ok(())); }
+            }
+            (screen.sqlite.clone(), screen.objects.get(screen.active_window.1).map(|o| o.name.clone()))
+        };
+        let show_internal = !self.show_internal;
+        let mut list = match CSGScreen::new_table_list(sqlite, self.width, self.height, &self.theme, show_internal) {
+            Ok(list) => { list },
+            Err(err) => { return Some(Err(err)); }
+        };
+        self.show_internal = show_internal;
+        // Stay on the same object if it is still listed
+        let y = selected.and_then(|name| list.objects.iter().position(|o| o.name == name)).unwrap_or(0);
+        list.active_window = (0, y);
+        list.scroll_to_active();
+        self.screens[self.active_screen].clear_all();
+        match list.load_visible() {
+            Ok(_) => { },
+            Err(err) => { return Some(Err(err)); }
+        };
+        list.write_all();
+        self.screens[self.active_screen] = list;
+        Some(Ok(()))
+    }
+
+    /// Shows the schema of the object selected on a table list
+    pub fn handle_schema(&mut self) -> Option<Result<(), SqliteError>> {
+        let (sqlite, object) = {
+            let screen = &self.screens[self.active_screen];
+            match screen.kind {
+                ScreenKind::TableList => { },
+                _ => { return Some(Ok(())); }
+            }
+            match screen.objects.get(screen.active_window.1) {
+                Some(object) => { (screen.sqlite.clone(), object.clone()) },
+                None => { return Some(Ok(())); }
+            }
+        };
+        // Indexes and triggers are shown as part of their table
+        match CSGScreen::new_schema(sqlite, self.width, self.height, &self.theme, object.table.as_slice()) {
+            Ok(schema) => {
+                self.screens[self.active_screen].clear_all();
+                self.add_screen(schema);
+                Some(Ok(()))
+            },
+            Err(err) => { Some(Err(err)) }
+        }
+    }
+
+    /// Opens the SQL prompt, from a new console unless one is showing
+    pub fn handle_console(&mut self) -> Option<Result<(), SqliteError>> {
+        let sqlite = {
+            let screen = &self.screens[self.active_screen];
+            match screen.kind {
+                ScreenKind::Console => { None },
+                ScreenKind::DatabaseList => { Some(self.databases[screen.active_window.1].clone()) },
+                _ => { Some(screen.sqlite.clone()) }
+            }
+        };
+        match sqlite {
+            Some(sqlite) => {
+                match CSGScreen::new_console(sqlite, self.width, self.height, &self.theme) {
+                    Ok(console) => {
+                        self.screens[self.active_screen].clear_all();
+                        self.add_screen(console);
+                    },
+                    Err(err) => { return Some(Err(err)); }
+                }
+            },
+            None => { }
+        }
+        self.open_sql_prompt();
+        Some(Ok(()))
+    }
+
+    // Starts typing a statement on the active screen
+    fn open_sql_prompt(&mut self) {
+        let prompt = Prompt {
+            label : "sql> ".to_string(),
+            editor : LineEditor::with_history(self.history.as_slice()),
+            action : PromptAction::RunSql
+        };
+        set_cursor_visible(true);
+        self.screens[self.active_screen].show_prompt(&prompt);
+        self.prompt = Some(prompt);
+    }
+
+    /// Runs `sql` in the console and shows what it returned, or how many
+    /// rows it changed. Errors are shown on the status line.
+    pub fn run_sql(&mut self, sql : &str) -> Option<Result<(), SqliteError>> {
+        if sql.trim().is_empty() {
+            self.screens[self.active_screen].write_status();
+            return Some(Ok(()));
+        }
+        if self.history.last().map(|last| last.as_slice()) != Some(sql) {
+            self.history.push(sql.to_string());
+        }
+        if self.history.len() > self.history_size {
+            let excess = self.history.len() - self.history_size;
+            let kept : Vec<String> = self.history.drain().skip(excess).collect();
+            self.history = kept;
+        }
+
+        let sqlite = self.screens[self.active_screen].sqlite.clone();
+        let before = sqlite.total_changes();
+        let results = match sqlite.exec(sql) {
+            Ok(results) => { results },
+            Err(err) => {
+                self.screens[self.active_screen].show_message(format!("{}", err));
+                return Some(Ok(()));
+            }
+        };
+        let message = match results.num_cols {
+            Some(n) if n > 0 => { format!("{} rows", results.rows.len()) },
+            _ => { format!("{} rows changed", sqlite.total_changes() - before) }
+        };
+        match CSGScreen::new_result_grid(sqlite, self.width, self.height, &self.theme, results,
+                                         ScreenKind::Console, sql.to_string()) {
+            Ok(mut console) => {
+                self.screens[self.active_screen].clear_all();
+                console.write_all();
+                console.show_message(message);
+                self.screens[self.active_screen] = console;
+                Some(Ok(()))
+            },
+            Err(err) => { Some(Err(err)) }
+        }
+    }
+
+    // Feeds a key to the open prompt, acting on the text once it is entered
+    fn handle_prompt_key(&mut self, key : Key) -> Option<Result<(), SqliteError>> {
+        let mut prompt = match self.prompt.take() {
+            Some(prompt) => { prompt },
+            None => { return Some(Ok(())); }
+        };
+        match prompt.editor.handle_key(key) {
+            EditEvent::Continue => {
+                self.screens[self.active_screen].show_prompt(&prompt);
+                self.prompt = Some(prompt);
+                Some(Ok(()))
+            },
+            EditEvent::Cancel => {
+                set_cursor_visible(false);
+                self.screens[self.active_screen].write_status();
+                Some(Ok(()))
+            },
+            EditEvent::Done(text) => {
+                set_cursor_visible(false);
+                self.finish_prompt(prompt.action, text)
+            }
+        }
+    }
+
+    // Carries out a prompt, SQLite errors are shown rather than ending the session
+    fn finish_prompt(&mut self, action : PromptAction, text : String) -> Option<Result<(), SqliteError>> {
+        if let PromptAction::RunSql = action {
+            return self.run_sql(text.as_slice());
+        }
+        if let PromptAction::Export = action {
+            // The suggested name may well be taken, ask before replacing it
+            let path = text.trim().to_string();
+            if Path::new(path.as_slice()).exists() {
+                let prompt = Prompt {
+                    label : format!("{} exists, overwrite? (y/n) ", path),
+                    editor : LineEditor::new(""),
+                    action : PromptAction::Overwrite(path)
+                };
+                set_cursor_visible(true);
+                self.screens[self.active_screen].show_prompt(&prompt);
+                self.prompt = Some(prompt);
+                return Some(Ok(()));
+            }
+        }
+        let screen = &mut self.screens[self.active_screen];
+        let result = match action {
+            PromptAction::EditCell(x, y, like) => {
+                screen.update_cell(x, y, parse_like(text, &like))
+            },
+            PromptAction::DeleteRow(y) => {
+                match is_yes(text.as_slice()) {
+                    true => { screen.delete_row(y) },
+                    false => {
+                        screen.write_status();
+                        Ok(())
+                    }
+                }
+            },
+            PromptAction::GoToOffset => {
+                match parse_offset(text.as_slice()) {
+                    Some(offset) => { screen.go_to_offset(offset) },
+                    None => {
+                        screen.show_message(format!("not a hex offset: {}", text));
+                        Ok(())
+                    }
+                }
+            },
+            PromptAction::Export => {
+                screen.export(text.trim(), self.csv_delimiter);
+                Ok(())
+            },
+            PromptAction::Overwrite(path) => {
+                match is_yes(text.as_slice()) {
+                    true => { screen.export(path.as_slice(), self.csv_delimiter); },
+                    false => { screen.write_status(); }
+                }
+                Ok(())
+            },
+            PromptAction::RunSql => { Ok(()) }
+        };
+        match result {
+            Ok(_) => { },
+            Err(err) => { screen.show_message(format!("{}", err)); }
+        }
+        Some(Ok(()))
+    }
+
     // Adds a new screen and sets it as active
     pub fn add_screen(&mut self, s : CSGScreen) {
         self.screens.push(s);
@@ -406,13 +2033,12 @@ impl CSG {
     }
 
     /// Set method for the active window
-    pub fn set_active_window(&mut self, prev : (i64, i64), next : (i64, i64)) {
+    pub fn set_active_window(&mut self, prev : (i64, i64), next : (i64, i64)) -> Result<(), SqliteError> {
         self.screens[self.active_screen].set_active_window(prev, next)
     }
 
-    // Read characters within the context of the current window
+    // Read characters within the context of the current screen
     pub fn read_current_window(&self) -> usize {
-        let current_window : &CSGWindow = self.get_active_window().unwrap();
-        current_window.window.read_in()
+        self.screens[self.active_screen].read_in()
     }
 }
\ No newline at end of file
@@ -0,0 +1,69 @@
+/// Writing rows out as CSV, as RFC 4180 describes it
+
+use std::io::{Writer, IoResult};
+use sqlite::{Value};
+
+/// Writes rows of values as CSV. Fields holding the delimiter, a quote or a
+/// line break are quoted, with quotes inside doubled, and lines end in CRLF.
+/// NULL is written as an empty field and the empty string as a quoted one,
+/// so the two can be told apart. BLOBs are written in hex.
+pub struct CsvWriter<W> {
+    writer : W,
+    delimiter : char
+}
+
+impl<W : Writer> CsvWriter<W> {
+    /// Constructor, fields are separated by `delimiter`
+    pub fn new(writer : W, delimiter : char) -> CsvWriter<W> {
+        CsvWriter {
+            writer : writer,
+            delimiter : delimiter
+        }
+    }
+
+    /// Writes a line of column names
+    pub fn write_header(&mut self, names : &[String]) -> IoResult<()> {
+        let fields : Vec<String> = names.iter().map(|name| self.field(name.as_slice())).collect();
+        self.write_line(fields)
+    }
+
+    /// Writes a line of values
+    pub fn write_row(&mut self, row : &[Value]) -> IoResult<()> {
+        let fields : Vec<String> = row.iter().map(|value| {
+            match *value {
+                Value::Null => "".to_string(),
+                Value::Text(ref s) if s.is_empty() => "\"\"".to_string(),
+                Value::Text(ref s) => self.field(s.as_slice()),
+                Value::Blob(ref b) => b.iter().map(|byte| format!("{:02x}", byte)).collect(),
+                _ => self.field(value.to_text().as_slice())
+            }
+        }).collect();
+        self.write_line(fields)
+    }
+
+    /// Flushes what was written and hands back the writer
+    pub fn finish(mut self) -> IoResult<W> {
+        match self.writer.flush() {
+            Ok(_) => { Ok(self.writer) },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// `text` as a field, quoted when it has to be
+    fn field(&self, text : &str) -> String {
+        let delimiter = self.delimiter;
+        let needs_quotes = text.chars().any(|c| c == delimiter || c == '"' || c == '\r' || c == '\n');
+        match needs_quotes {
+            true => format!("\"{}\"", text.replace("\"", "\"\"")),
+            false => text.to_string()
+        }
+    }
+
+    /// Writes `fields` separated by the delimiter as one line
+    fn write_line(&mut self, fields : Vec<String>) -> IoResult<()> {
+        let mut delimiter = String::new();
+        delimiter.push(self.delimiter);
+        let line = fields.connect(delimiter.as_slice());
+        self.writer.write_str(line.as_slice()).and_then(|_| self.writer.write_str("\r\n"))
+    }
+}
@@ -2,10 +2,104 @@
 
 extern crate libc;
 
-use curses::libc::{c_char, c_int};
+use curses::libc::{c_char, c_int, c_short, c_uchar};
 use cext::{TRUE, str_to_cstr};
 
 const CURSOR_INVISIBLE : c_int = 0;
+const CURSOR_VISIBLE : c_int = 1;
+
+// Locale category for character encoding, from locale.h
+#[cfg(target_os = "macos")]
+const LC_CTYPE : c_int = 2;
+#[cfg(not(target_os = "macos"))]
+const LC_CTYPE : c_int = 0;
+
+// Key codes returned by wgetch when keypad is on
+pub const KEY_DOWN : usize = 258;
+pub const KEY_UP : usize = 259;
+pub const KEY_LEFT : usize = 260;
+pub const KEY_RIGHT : usize = 261;
+pub const KEY_HOME : usize = 262;
+pub const KEY_BACKSPACE : usize = 263;
+pub const KEY_DC : usize = 330;
+pub const KEY_NPAGE : usize = 338;
+pub const KEY_PPAGE : usize = 339;
+pub const KEY_ENTER : usize = 343;
+pub const KEY_END : usize = 360;
+pub const KEY_RESIZE : usize = 410;
+
+/// A keystroke, as read by `Window::read_key`
+#[derive(Clone, Copy, PartialEq, Show)]
+pub enum Key {
+    /// A printable ASCII character
+    Char(char),
+    /// One byte of a character that takes several in UTF-8
+    Byte(u8),
+    /// A letter typed with Control held
+    Ctrl(char),
+    Enter,
+    Escape,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// The terminal changed size
+    Resize,
+    /// Anything else, by its curses key code
+    Other(usize)
+}
+
+impl Key {
+    /// Translates a key code returned by wgetch
+    pub fn from_code(c : usize) -> Key {
+        match c {
+            10 | 13 | KEY_ENTER => Key::Enter,
+            27 => Key::Escape,
+            8 | 127 | KEY_BACKSPACE => Key::Backspace,
+            1...26 => Key::Ctrl((b'a' + c as u8 - 1) as char),
+            32...126 => Key::Char(c as u8 as char),
+            128...255 => Key::Byte(c as u8),
+            KEY_DC => Key::Delete,
+            KEY_UP => Key::Up,
+            KEY_DOWN => Key::Down,
+            KEY_LEFT => Key::Left,
+            KEY_RIGHT => Key::Right,
+            KEY_HOME => Key::Home,
+            KEY_END => Key::End,
+            KEY_PPAGE => Key::PageUp,
+            KEY_NPAGE => Key::PageDown,
+            KEY_RESIZE => Key::Resize,
+            _ => Key::Other(c)
+        }
+    }
+}
+
+/// Display attributes of text, combined with `|`
+pub type Attr = c_int;
+
+// Attributes, as ncurses lays them out
+pub const A_NORMAL : Attr = 0;
+pub const A_UNDERLINE : Attr = 1 << 17;
+pub const A_REVERSE : Attr = 1 << 18;
+pub const A_DIM : Attr = 1 << 20;
+pub const A_BOLD : Attr = 1 << 21;
+
+// Colors
+pub const COLOR_DEFAULT : i16 = -1;
+pub const COLOR_BLACK : i16 = 0;
+pub const COLOR_RED : i16 = 1;
+pub const COLOR_GREEN : i16 = 2;
+pub const COLOR_YELLOW : i16 = 3;
+pub const COLOR_BLUE : i16 = 4;
+pub const COLOR_MAGENTA : i16 = 5;
+pub const COLOR_CYAN : i16 = 6;
+pub const COLOR_WHITE : i16 = 7;
 
 // Wrapper for opaque struct
 #[repr(C)]
@@ -18,9 +112,14 @@ pub struct Window {
 }
 
 /// Opaque curses struct, needed for setup & teardown
-pub struct Curses;
+pub struct Curses {
+    /// The whole terminal
+    stdscr : *const c_curses_window
+}
 
-#[link(name="ncurses")]
+// The wide character build, the narrow one draws UTF-8 a byte at a time
+// and gets the width of anything past ASCII wrong
+#[link(name="ncursesw")]
 extern {
     // Initialization & teardown
     fn initscr() -> *const c_curses_window;
@@ -28,8 +127,18 @@ extern {
     fn endwin() -> c_int;
 
     // Display
-    fn wprintw(win : *const c_curses_window, fmt : *const c_char) -> c_int;
+    fn waddstr(win : *const c_curses_window, s : *const c_char) -> c_int;
+    fn wattrset(win : *const c_curses_window, attrs : c_int) -> c_int;
     fn wclear(win : *const c_curses_window) -> c_int;
+    fn wmove(win : *const c_curses_window, y : c_int, x : c_int) -> c_int;
+    fn getmaxy(win : *const c_curses_window) -> c_int;
+    fn getmaxx(win : *const c_curses_window) -> c_int;
+
+    // Color
+    fn has_colors() -> c_uchar;
+    fn start_color() -> c_int;
+    fn use_default_colors() -> c_int;
+    fn init_pair(pair : c_short, f : c_short, b : c_short) -> c_int;
 
     // Character input
     fn cbreak();
@@ -47,6 +156,10 @@ extern {
     fn delwin(window : *const c_curses_window) -> c_int;
 }
 
+extern {
+    fn setlocale(category : c_int, locale : *const c_char) -> *const c_char;
+}
+
 impl Window {
     pub fn new(nlines : usize, 
                ncols : usize, 
@@ -71,9 +184,24 @@ impl Window {
 
     /// Writes text to a window and refresh
     pub fn write(&self, s : &str) {
+        self.write_with(s, A_NORMAL)
+    }
+
+    /// Writes text to a window with attributes `attr` and refresh
+    pub fn write_with(&self, s : &str, attr : Attr) {
         self.clear();
         let s = str_to_cstr(s);
-        unsafe { wprintw(self.window, s.as_ptr()); }
+        unsafe {
+            wattrset(self.window, attr);
+            waddstr(self.window, s.as_ptr());
+            wattrset(self.window, A_NORMAL);
+        }
+        self.refresh()
+    }
+
+    /// Moves the cursor within the window and refresh
+    pub fn move_cursor(&self, y : usize, x : usize) {
+        unsafe { wmove(self.window, y as c_int, x as c_int); }
         self.refresh()
     }
 
@@ -88,6 +216,11 @@ impl Window {
         let c : c_int = unsafe { wgetch(self.window) };
         c as usize
     }
+
+    /// Reads in a keystroke as a `Key`
+    pub fn read_key(&self) -> Key {
+        Key::from_code(self.read_in())
+    }
 }
 
 impl Drop for Window {
@@ -100,16 +233,70 @@ impl Drop for Window {
 impl Curses {
     /// Initializes curses
     pub fn new() -> Curses {
-        unsafe { 
-            initscr();
+        // Take the encoding from the environment so UTF-8 text is drawn
+        let locale = str_to_cstr("");
+        let stdscr = unsafe { 
+            setlocale(LC_CTYPE, locale.as_ptr());
+            let stdscr = initscr();
             curs_set(CURSOR_INVISIBLE);
             cbreak();
             noecho();
             nonl();
+            stdscr
+        };
+        Curses {
+            stdscr : stdscr
+        }
+    }
+
+    /// Size of the terminal as (columns, lines), zero if it is unknown
+    pub fn size(&self) -> (usize, usize) {
+        let (lines, cols) = unsafe { (getmaxy(self.stdscr), getmaxx(self.stdscr)) };
+        if lines < 0 || cols < 0 {
+            return (0, 0);
+        }
+        (cols as usize, lines as usize)
+    }
+
+    /// Blanks the whole terminal, for redrawing after a resize
+    pub fn clear(&self) {
+        unsafe {
+            wclear(self.stdscr);
+            wrefresh(self.stdscr);
         }
-        Curses
     }
 
+    /// Turns on colors, returns false if the terminal has none
+    pub fn start_colors(&self) -> bool {
+        unsafe {
+            if has_colors() == 0 {
+                return false;
+            }
+            start_color();
+            use_default_colors();
+        }
+        true
+    }
+
+    /// Sets the foreground and background colors of color pair `pair`
+    pub fn init_pair(&self, pair : i16, fg : i16, bg : i16) {
+        unsafe { init_pair(pair as c_short, fg as c_short, bg as c_short); }
+    }
+}
+
+/// Attribute drawing text in color pair `pair`
+pub fn color_pair(pair : i16) -> Attr {
+    (pair as Attr) << 8
+}
+
+/// Shows or hides the terminal cursor
+pub fn set_cursor_visible(visible : bool) {
+    unsafe {
+        curs_set(match visible {
+            true => CURSOR_VISIBLE,
+            false => CURSOR_INVISIBLE
+        });
+    }
 }
 
 impl Drop for Curses {
@@ -0,0 +1,167 @@
+/// Single line text editing, fed one keystroke at a time
+
+use std::str;
+use curses::{Key};
+
+/// What a keystroke did to the line
+#[derive(Clone, PartialEq, Show)]
+pub enum EditEvent {
+    /// Still editing
+    Continue,
+    /// Enter was pressed, here is the text
+    Done(String),
+    /// Escape was pressed
+    Cancel
+}
+
+/// A line of text and a cursor
+#[derive(Clone)]
+pub struct LineEditor {
+    chars : Vec<char>,
+    /// Position of the cursor, in characters
+    cursor : usize,
+    /// Bytes of a multi-byte UTF-8 character read so far
+    pending : Vec<u8>,
+    /// Earlier lines, oldest first, recalled with the up and down keys
+    history : Vec<String>,
+    /// Which line of the history is shown, `history.len()` for the new one
+    history_pos : usize,
+    /// The new line, kept while earlier ones are shown
+    draft : String
+}
+
+impl LineEditor {
+    /// Constructor, the cursor starts after `initial`
+    pub fn new(initial : &str) -> LineEditor {
+        let chars : Vec<char> = initial.chars().collect();
+        LineEditor {
+            cursor : chars.len(),
+            chars : chars,
+            pending : Vec::new(),
+            history : Vec::new(),
+            history_pos : 0,
+            draft : "".to_string()
+        }
+    }
+
+    /// Constructor for an empty line that can recall `history`
+    pub fn with_history(history : &[String]) -> LineEditor {
+        let mut editor = LineEditor::new("");
+        editor.history = history.to_vec();
+        editor.history_pos = history.len();
+        editor
+    }
+
+    /// Text typed so far
+    pub fn text(&self) -> String {
+        self.chars.iter().map(|c| *c).collect()
+    }
+
+    /// Position of the cursor, in characters
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Applies a keystroke
+    pub fn handle_key(&mut self, key : Key) -> EditEvent {
+        match key {
+            Key::Enter => {
+                return EditEvent::Done(self.text());
+            },
+            Key::Escape => {
+                return EditEvent::Cancel;
+            },
+            Key::Left => {
+                if self.cursor > 0 {
+                    self.cursor = self.cursor - 1;
+                }
+            },
+            Key::Right => {
+                if self.cursor < self.chars.len() {
+                    self.cursor = self.cursor + 1;
+                }
+            },
+            Key::Up => {
+                if self.history_pos > 0 {
+                    if self.history_pos == self.history.len() {
+                        self.draft = self.text();
+                    }
+                    self.history_pos = self.history_pos - 1;
+                    let line = self.history[self.history_pos].clone();
+                    self.set_text(line.as_slice());
+                }
+            },
+            Key::Down => {
+                if self.history_pos < self.history.len() {
+                    self.history_pos = self.history_pos + 1;
+                    let line = match self.history.get(self.history_pos) {
+                        Some(line) => { line.clone() },
+                        None => { self.draft.clone() }
+                    };
+                    self.set_text(line.as_slice());
+                }
+            },
+            Key::Home | Key::Ctrl('a') => {
+                self.cursor = 0;
+            },
+            Key::End | Key::Ctrl('e') => {
+                self.cursor = self.chars.len();
+            },
+            Key::Backspace => {
+                if self.cursor > 0 {
+                    self.cursor = self.cursor - 1;
+                    self.chars.remove(self.cursor);
+                }
+            },
+            Key::Delete => {
+                if self.cursor < self.chars.len() {
+                    self.chars.remove(self.cursor);
+                }
+            },
+            Key::Ctrl('u') => {
+                self.chars.truncate(0);
+                self.cursor = 0;
+            },
+            Key::Char(c) => {
+                self.insert(c);
+            },
+            Key::Byte(b) => {
+                self.insert_byte(b);
+            },
+            _ => { }
+        }
+        EditEvent::Continue
+    }
+
+    /// Replaces the line, leaving the cursor at its end
+    fn set_text(&mut self, text : &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    /// Inserts a character at the cursor
+    fn insert(&mut self, c : char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor = self.cursor + 1;
+    }
+
+    /// Collects the bytes of a UTF-8 character, inserting it once complete
+    fn insert_byte(&mut self, b : u8) {
+        self.pending.push(b);
+        let decoded : Option<Vec<char>> = match str::from_utf8(self.pending.as_slice()) {
+            Ok(s) => { Some(s.chars().collect()) },
+            Err(_) => { None }
+        };
+        match decoded {
+            Some(chars) => {
+                self.pending.truncate(0);
+                for c in chars.into_iter() {
+                    self.insert(c);
+                }
+            },
+            // No UTF-8 character is longer than 4 bytes
+            None if self.pending.len() >= 4 => { self.pending.truncate(0); },
+            None => { }
+        }
+    }
+}
\ No newline at end of file
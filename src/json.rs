@@ -0,0 +1,204 @@
+/// Pretty-printing of JSON held in cells
+
+/// Spaces per level of nesting
+static INDENT : usize = 2;
+
+/// Deepest nesting laid out, anything deeper is left as plain text rather
+/// than run out of stack
+static MAX_DEPTH : usize = 512;
+
+/// `text` with each member and element on a line of its own, or None when it
+/// isn't a JSON object or array. Members keep their order and strings are
+/// copied as written.
+pub fn pretty(text : &str) -> Option<String> {
+    let mut printer = Printer {
+        chars : text.chars().collect(),
+        pos : 0,
+        out : String::new()
+    };
+    printer.skip_space();
+    match printer.peek() {
+        Some('{') | Some('[') => { },
+        _ => { return None; }
+    }
+    if !printer.value(0) {
+        return None;
+    }
+    printer.skip_space();
+    match printer.pos == printer.chars.len() {
+        true => Some(printer.out),
+        false => None
+    }
+}
+
+/// Reads JSON a character at a time, writing it out laid out
+struct Printer {
+    chars : Vec<char>,
+    pos : usize,
+    out : String
+}
+
+impl Printer {
+    /// Character at the cursor
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|c| *c)
+    }
+
+    /// Moves the cursor past whitespace, which is dropped
+    fn skip_space(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ') | Some('\t') | Some('\n') | Some('\r') => { self.pos += 1; },
+                _ => { break; }
+            }
+        }
+    }
+
+    /// Starts a line indented `depth` levels
+    fn newline(&mut self, depth : usize) {
+        self.out.push('\n');
+        for _ in (0..depth * INDENT) {
+            self.out.push(' ');
+        }
+    }
+
+    /// Copies the value at the cursor, nested `depth` levels deep. Returns
+    /// false if it isn't valid.
+    fn value(&mut self, depth : usize) -> bool {
+        if depth > MAX_DEPTH {
+            return false;
+        }
+        self.skip_space();
+        match self.peek() {
+            Some('{') => self.container('}', depth, true),
+            Some('[') => self.container(']', depth, false),
+            Some('"') => self.string(),
+            Some(_) => self.literal(),
+            None => false
+        }
+    }
+
+    /// Copies an object or array, which ends at `close`
+    fn container(&mut self, close : char, depth : usize, is_object : bool) -> bool {
+        let open = self.chars[self.pos];
+        self.pos += 1;
+        self.out.push(open);
+        self.skip_space();
+        // Empty ones stay on one line
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            self.out.push(close);
+            return true;
+        }
+        loop {
+            self.newline(depth + 1);
+            if is_object {
+                self.skip_space();
+                if self.peek() != Some('"') || !self.string() {
+                    return false;
+                }
+                self.skip_space();
+                if self.peek() != Some(':') {
+                    return false;
+                }
+                self.pos += 1;
+                self.out.push_str(": ");
+            }
+            if !self.value(depth + 1) {
+                return false;
+            }
+            self.skip_space();
+            match self.peek() {
+                Some(',') => {
+                    self.pos += 1;
+                    self.out.push(',');
+                },
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    self.newline(depth);
+                    self.out.push(close);
+                    return true;
+                },
+                _ => { return false; }
+            }
+        }
+    }
+
+    /// Copies a string, escapes and all
+    fn string(&mut self) -> bool {
+        self.pos += 1;
+        self.out.push('"');
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    self.out.push('"');
+                    return true;
+                },
+                Some('\\') => {
+                    self.pos += 1;
+                    self.out.push('\\');
+                    match self.peek() {
+                        Some(c) => {
+                            self.pos += 1;
+                            self.out.push(c);
+                        },
+                        None => { return false; }
+                    }
+                },
+                // Control characters have to be escaped
+                Some(c) if c < ' ' => { return false; },
+                Some(c) => {
+                    self.pos += 1;
+                    self.out.push(c);
+                },
+                None => { return false; }
+            }
+        }
+    }
+
+    /// Copies a number, true, false or null
+    fn literal(&mut self) -> bool {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(c) if c.is_alphanumeric() || c == '-' || c == '+' || c == '.' => { self.pos += 1; },
+                _ => { break; }
+            }
+        }
+        let word : String = self.chars.slice(start, self.pos).iter().map(|c| *c).collect();
+        match word.as_slice() {
+            "true" | "false" | "null" => { },
+            number if is_number(number) => { },
+            _ => { return false; }
+        }
+        self.out.push_str(word.as_slice());
+        true
+    }
+}
+
+/// Whether `word` is written the way JSON writes numbers
+fn is_number(word : &str) -> bool {
+    let unsigned = if word.starts_with("-") { word.slice_from(1) } else { word };
+    let (mantissa, exponent) = match unsigned.find(|c : char| c == 'e' || c == 'E') {
+        Some(i) => { (unsigned.slice_to(i), Some(unsigned.slice_from(i + 1))) },
+        None => { (unsigned, None) }
+    };
+    let (whole, fraction) = match mantissa.find('.') {
+        Some(i) => { (mantissa.slice_to(i), Some(mantissa.slice_from(i + 1))) },
+        None => { (mantissa, None) }
+    };
+    // No leading zeros
+    let whole_ok = is_digits(whole) && (whole == "0" || !whole.starts_with("0"));
+    let exponent_ok = match exponent {
+        Some(e) if e.starts_with("+") || e.starts_with("-") => is_digits(e.slice_from(1)),
+        Some(e) => is_digits(e),
+        None => true
+    };
+    whole_ok && fraction.map(is_digits).unwrap_or(true) && exponent_ok
+}
+
+/// Whether `s` is one or more decimal digits
+fn is_digits(s : &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_digit(10))
+}
@@ -0,0 +1,346 @@
+/// Bindings of keys and key sequences to named actions
+
+use std::mem;
+use curses::{Key};
+use config::{parse_strings, strip_comment};
+
+/// Something a key can be bound to
+#[derive(Clone, Copy, PartialEq, Show)]
+pub enum Action {
+    MoveLeft,
+    MoveDown,
+    MoveUp,
+    MoveRight,
+    PageUp,
+    PageDown,
+    FirstRow,
+    LastRow,
+    FirstColumn,
+    LastColumn,
+    /// Open the selected database, table or view
+    DrillIn,
+    /// Edit the selected cell
+    Edit,
+    /// Close the screen, quitting from the first one
+    Back,
+    InsertRow,
+    DeleteRow,
+    /// Add the row typed into an insert form
+    WriteRow,
+    Console,
+    Schema,
+    ToggleInternal,
+    /// Make the selected column wider or narrower
+    WidenColumn,
+    NarrowColumn,
+    /// Show the whole of the selected value
+    View,
+    /// Jump to an offset in a BLOB
+    GoToOffset,
+    /// Write the rows of a table or result to a CSV file
+    Export
+}
+
+/// Every action, with the name it is bound by
+static ACTIONS : [(Action, &'static str); 24] = [
+    (Action::MoveLeft, "move-left"),
+    (Action::MoveDown, "move-down"),
+    (Action::MoveUp, "move-up"),
+    (Action::MoveRight, "move-right"),
+    (Action::PageUp, "page-up"),
+    (Action::PageDown, "page-down"),
+    (Action::FirstRow, "first-row"),
+    (Action::LastRow, "last-row"),
+    (Action::FirstColumn, "first-column"),
+    (Action::LastColumn, "last-column"),
+    (Action::DrillIn, "drill-in"),
+    (Action::Edit, "edit"),
+    (Action::Back, "back"),
+    (Action::InsertRow, "insert-row"),
+    (Action::DeleteRow, "delete-row"),
+    (Action::WriteRow, "write-row"),
+    (Action::Console, "console"),
+    (Action::Schema, "schema"),
+    (Action::ToggleInternal, "toggle-internal"),
+    (Action::WidenColumn, "widen-column"),
+    (Action::NarrowColumn, "narrow-column"),
+    (Action::View, "view"),
+    (Action::GoToOffset, "go-to-offset"),
+    (Action::Export, "export")
+];
+
+/// Bindings used unless they are replaced
+static DEFAULT_BINDINGS : [(Action, &'static str); 24] = [
+    (Action::MoveLeft, "h Left"),
+    (Action::MoveDown, "j Down"),
+    (Action::MoveUp, "k Up"),
+    (Action::MoveRight, "l Right"),
+    (Action::PageUp, "PageUp"),
+    (Action::PageDown, "PageDown"),
+    (Action::FirstRow, "g Home"),
+    (Action::LastRow, "G End"),
+    (Action::FirstColumn, "0"),
+    (Action::LastColumn, "$"),
+    (Action::DrillIn, "Enter"),
+    (Action::Edit, "e"),
+    (Action::Back, "q"),
+    (Action::InsertRow, "i"),
+    (Action::DeleteRow, "d"),
+    (Action::WriteRow, "w"),
+    (Action::Console, ":"),
+    (Action::Schema, "s"),
+    (Action::ToggleInternal, "."),
+    (Action::WidenColumn, ">"),
+    (Action::NarrowColumn, "<"),
+    (Action::View, "v"),
+    (Action::GoToOffset, "o"),
+    (Action::Export, "x")
+];
+
+/// Named keys, as written in key sequences
+static KEY_NAMES : [(Key, &'static str); 16] = [
+    (Key::Enter, "Enter"),
+    (Key::Escape, "Esc"),
+    (Key::Backspace, "Backspace"),
+    (Key::Delete, "Delete"),
+    (Key::Up, "Up"),
+    (Key::Down, "Down"),
+    (Key::Left, "Left"),
+    (Key::Right, "Right"),
+    (Key::Home, "Home"),
+    (Key::End, "End"),
+    (Key::PageUp, "PageUp"),
+    (Key::PageDown, "PageDown"),
+    (Key::Char(' '), "Space"),
+    (Key::Ctrl('i'), "Tab"),
+    (Key::Escape, "Escape"),
+    (Key::Enter, "Return")
+];
+
+impl Action {
+    /// Action called `name`
+    pub fn from_name(name : &str) -> Option<Action> {
+        ACTIONS.iter().find(|&&(_, n)| n == name).map(|&(action, _)| action)
+    }
+
+    /// Name the action is bound by
+    pub fn name(&self) -> &'static str {
+        ACTIONS.iter().find(|&&(action, _)| action == *self).map(|&(_, n)| n).unwrap_or("")
+    }
+}
+
+/// Reads a key sequence. Keys are separated by spaces and are either a name
+/// such as `Left`, `PageDown` or `C-n` for Control and n, or a run of
+/// characters each typed in turn, so `gg` is g twice.
+pub fn parse_sequence(text : &str) -> Result<Vec<Key>, String> {
+    let mut keys : Vec<Key> = Vec::new();
+    for word in text.split(' ').filter(|word| !word.is_empty()) {
+        match KEY_NAMES.iter().find(|&&(_, name)| name == word) {
+            Some(&(key, _)) => {
+                keys.push(key);
+                continue;
+            },
+            None => { }
+        }
+        if word.starts_with("C-") && word.chars().count() == 3 {
+            match word.chars().nth(2).unwrap().to_lowercase() {
+                c @ 'a'...'z' => {
+                    keys.push(Key::Ctrl(c));
+                    continue;
+                },
+                _ => { return Err(format!("`{}` is not a Control key", word)); }
+            }
+        }
+        for c in word.chars() {
+            match c {
+                ' '...'~' => { keys.push(Key::Char(c)); },
+                _ => { return Err(format!("`{}` can't be typed as a key", c)); }
+            }
+        }
+    }
+    match keys.is_empty() {
+        true => Err("empty key sequence".to_string()),
+        false => Ok(keys)
+    }
+}
+
+/// Key sequences and what they do, along with the keys of a sequence typed
+/// so far
+#[derive(Clone)]
+pub struct Keymap {
+    bindings : Vec<(Vec<Key>, Action)>,
+    /// Sequences read since overlaps were last checked, as written and
+    /// with the line they are on
+    loaded : Vec<(Vec<Key>, String, usize)>,
+    pending : Vec<Key>
+}
+
+impl Keymap {
+    /// Constructor for the built-in bindings
+    pub fn new() -> Keymap {
+        let mut keymap = Keymap {
+            bindings : Vec::new(),
+            loaded : Vec::new(),
+            pending : Vec::new()
+        };
+        for &(action, keys) in DEFAULT_BINDINGS.iter() {
+            for key in keys.split(' ') {
+                keymap.bindings.push((parse_sequence(key).unwrap(), action));
+            }
+        }
+        keymap
+    }
+
+    /// Replaces the bindings of `action` with `sequences`
+    pub fn bind(&mut self, action : Action, sequences : Vec<Vec<Key>>) {
+        self.bindings.retain(|&(_, bound)| bound != action);
+        for sequence in sequences.into_iter() {
+            // A sequence does one thing, whatever it was bound to before
+            self.bindings.retain(|&(ref keys, _)| *keys != sequence);
+            self.bindings.push((sequence, action));
+        }
+    }
+
+    /// Reads bindings over the built-in ones, one action per line:
+    ///
+    /// ```text
+    /// # Emacs style movement
+    /// move-down = "C-n"
+    /// move-up = ["C-p", "Up"]
+    /// ```
+    ///
+    /// Each action listed loses its built-in bindings.
+    pub fn load(text : &str) -> Result<Keymap, String> {
+        let mut keymap = Keymap::new();
+        match keymap.load_into(text) {
+            Ok(_) => { Ok(keymap) },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Reads bindings over the ones already held, in the format of `load`
+    pub fn load_into(&mut self, text : &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            match self.load_line(line, i + 1) {
+                Ok(_) => { },
+                Err(err) => { return Err(format!("line {}: {}", i + 1, err)); }
+            }
+        }
+        match self.check_overlaps() {
+            Ok(_) => { Ok(()) },
+            Err((number, err)) => { Err(format!("line {}: {}", number, err)) }
+        }
+    }
+
+    /// Reads an `action = sequences` line, the one numbered `number`.
+    /// Overlaps aren't looked for until `check_overlaps`.
+    pub fn load_line(&mut self, line : &str, number : usize) -> Result<(), String> {
+        let (name, value) = match line.find('=') {
+            Some(i) => { (line.slice_to(i).trim(), line.slice_from(i + 1).trim()) },
+            None => { return Err("expected `action = \"keys\"`".to_string()); }
+        };
+        let action = match Action::from_name(name) {
+            Some(action) => { action },
+            None => { return Err(format!("unknown action `{}`", name)); }
+        };
+        let strings = match parse_strings(value) {
+            Ok(strings) => { strings },
+            Err(err) => { return Err(err); }
+        };
+        let mut sequences : Vec<Vec<Key>> = Vec::new();
+        for string in strings.iter() {
+            match parse_sequence(string.as_slice()) {
+                Ok(keys) => { sequences.push(keys); },
+                Err(err) => { return Err(err); }
+            }
+        }
+        self.bind(action, sequences.clone());
+        for (sequence, string) in sequences.into_iter().zip(strings.into_iter()) {
+            self.loaded.push((sequence, string, number));
+        }
+        Ok(())
+    }
+
+    /// Makes sure no sequence read since the last check is the start of
+    /// another, as sequences match as soon as they are typed and the longer
+    /// one would be unreachable. Of two that overlap, the one on the later
+    /// line is reported along with its line number, so the order of lines
+    /// doesn't matter.
+    pub fn check_overlaps(&mut self) -> Result<(), (usize, String)> {
+        let loaded = mem::replace(&mut self.loaded, Vec::new());
+        // Leaving out those rebound since or read again on a later line
+        let live : Vec<&(Vec<Key>, String, usize)> = loaded.iter().enumerate().filter(|&(i, entry)| {
+            self.lookup(entry.0.as_slice()).is_some() &&
+                !loaded.slice_from(i + 1).iter().any(|later| later.0 == entry.0)
+        }).map(|(_, entry)| entry).collect();
+        // In line order, so the first overlap found is the earliest
+        for &&(ref sequence, ref string, number) in live.iter() {
+            // Those on later lines are checked against this one in turn
+            let later : Vec<&Vec<Key>> = live.iter().filter(|entry| entry.2 > number).map(|entry| &entry.0).collect();
+            match self.overlapping(sequence.as_slice(), later.as_slice()) {
+                Some((other, true)) => {
+                    return Err((number, format!("`{}` starts with keys bound to {}", string, other.name())));
+                },
+                Some((other, false)) => {
+                    return Err((number, format!("`{}` is the start of keys bound to {}", string, other.name())));
+                },
+                None => { }
+            }
+        }
+        Ok(())
+    }
+
+    /// Action bound to a sequence `keys` starts with, or one starting with
+    /// `keys`, along with which of the two it is. Sequences in `skip` are
+    /// left out.
+    fn overlapping(&self, keys : &[Key], skip : &[&Vec<Key>]) -> Option<(Action, bool)> {
+        for &(ref bound, action) in self.bindings.iter() {
+            if skip.contains(&bound) {
+                continue;
+            }
+            if bound.len() < keys.len() && keys.starts_with(bound.as_slice()) {
+                return Some((action, true));
+            }
+            if bound.len() > keys.len() && bound.as_slice().starts_with(keys) {
+                return Some((action, false));
+            }
+        }
+        None
+    }
+
+    /// Action bound to exactly `keys`
+    pub fn lookup(&self, keys : &[Key]) -> Option<Action> {
+        self.bindings.iter().find(|&&(ref bound, _)| bound.as_slice() == keys).map(|&(_, action)| action)
+    }
+
+    /// Takes the next keystroke, returning the action once a whole sequence
+    /// is typed. A key that can't continue the sequence so far starts a new
+    /// one. Sequences are matched as soon as they are complete, so one that
+    /// is the start of another hides it.
+    pub fn feed(&mut self, key : Key) -> Option<Action> {
+        self.pending.push(key);
+        match self.lookup(self.pending.as_slice()) {
+            Some(action) => {
+                self.pending.truncate(0);
+                return Some(action);
+            },
+            None => { }
+        }
+        let is_prefix = self.bindings.iter().any(|&(ref bound, _)| {
+            bound.len() > self.pending.len() && bound.as_slice().starts_with(self.pending.as_slice())
+        });
+        if is_prefix {
+            return None;
+        }
+        let retry = self.pending.len() > 1;
+        self.pending.truncate(0);
+        match retry {
+            true => self.feed(key),
+            false => None
+        }
+    }
+}
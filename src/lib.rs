@@ -4,12 +4,16 @@
 #![allow(missing_copy_implementations)]
 #![allow(non_upper_case_globals)]
 #![allow(raw_pointer_derive)]
-#[macro_use]
-extern crate lazy_static;
 
 pub mod csgui;
 pub mod sqlite;
+pub mod pager;
 pub mod curses;
+pub mod editor;
+pub mod keymap;
+pub mod config;
+pub mod json;
+pub mod csv;
 pub mod cext;
 pub mod osext;
 #[cfg(test)]
@@ -1,34 +1,154 @@
 #![allow(unstable)]
 #![allow(dead_code)]
 extern crate csg;
+extern crate getopts;
 extern crate libc;
 
 use csg::csgui::{CSG};
+use csg::sqlite::{SqliteError, OpenMode};
+use csg::keymap::{Keymap};
+use csg::config::{Config};
+use std::io::File;
+use getopts::{optflag, optopt, getopts, usage};
+
+// Exit statuses for bad command lines and configuration, from sysexits.h
+static EX_USAGE : isize = 64;
+static EX_CONFIG : isize = 78;
 
 /// Entry point
 fn main() {
     let args = std::os::args();
-    if args.len() < 2 {
-        println!("usage: csg <file>");
+    let opts = [
+        optflag("r", "read-only", "open the databases read-only"),
+        optflag("w", "read-write", "open the databases read-write"),
+        optopt("t", "table", "open straight into a dump of TABLE", "TABLE"),
+        optopt("q", "query", "run SQL and show its results", "SQL"),
+        optopt("c", "config", "read settings from FILE", "FILE"),
+        optopt("k", "keymap", "read key bindings from FILE", "FILE"),
+        optflag("h", "help", "print this help and exit"),
+        optflag("V", "version", "print the version and exit")
+    ];
+    let matches = match getopts(args.tail(), &opts) {
+        Ok(matches) => { matches },
+        Err(f) => {
+            usage_error(format!("{}", f));
+            return;
+        }
+    };
+
+    if matches.opt_present("h") {
+        print!("{}", usage(brief(args[0].as_slice()).as_slice(), &opts));
+        return;
+    }
+    if matches.opt_present("V") {
+        println!("csg {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+    if matches.free.len() < 1 {
+        usage_error("no database given".to_string());
+        return;
+    }
+    let table = matches.opt_str("t");
+    let query = matches.opt_str("q");
+    if matches.free.len() > 1 && (table.is_some() || query.is_some()) {
+        usage_error("--table and --query need a single database".to_string());
         return;
     }
-    let result : Result<(), String> = {
-	    let mut csgui = match CSG::new(args[1].as_slice()) {
+    if matches.opt_present("r") && matches.opt_present("w") {
+        usage_error("--read-only and --read-write can't be used together".to_string());
+        return;
+    }
+    let config_path = matches.opt_str("c");
+    let mut config = match Config::load(config_path.as_ref().map(|path| path.as_slice())) {
+        Ok(config) => { config },
+        Err(err) => {
+            println!("csg: {}", err);
+            std::os::set_exit_status(EX_CONFIG);
+            return;
+        }
+    };
+    config.apply_env();
+    match matches.opt_str("k") {
+        Some(path) => {
+            match load_keymap(path.as_slice(), &mut config.keymap) {
+                Ok(_) => { },
+                Err(msg) => {
+                    println!("csg: {}: {}", path, msg);
+                    std::os::set_exit_status(EX_CONFIG);
+                    return;
+                }
+            }
+        },
+        None => { }
+    }
+    if matches.opt_present("r") {
+        config.mode = OpenMode::ReadOnly;
+    }
+    if matches.opt_present("w") {
+        config.mode = OpenMode::ReadWrite;
+    }
+
+    let result : Result<(), SqliteError> = {
+	    let mut csgui = match CSG::new_with_config(matches.free.as_slice(), config) {
 	    	Ok(csgui) => { csgui },
-	    	Err(msg) => { 
-	    		println!("{}", msg);
+	    	Err(err) => {
+	    		fail(err);
 	    		return;
 	    	}
 	    };
 
-    	csgui.run_forever()
+        let opened = match (table, query) {
+            (Some(table), _) => { csgui.open_table(table.as_slice()) },
+            (None, Some(query)) => { csgui.run_query(query.as_slice()) },
+            (None, None) => { Ok(()) }
+        };
+        match opened {
+            Ok(_) => { },
+            Err(err) => {
+                drop(csgui);
+                fail(err);
+                return;
+            }
+        }
+
+    	match csgui.run_forever() {
+    		Ok(_) => { csgui.close() },
+    		Err(err) => { Err(err) }
+    	}
     };
 
     match result {
     	Ok(_) => { },
-    	Err(msg) => { 
-    		println!("{}", msg);
+    	Err(err) => {
+    		fail(err);
     		return;
     	}
     }
+}
+
+/// First line of the help text
+fn brief(program : &str) -> String {
+    format!("usage: {} [options] <file>...", program)
+}
+
+/// Reads key bindings from the file at `path` over those in `keymap`
+fn load_keymap(path : &str, keymap : &mut Keymap) -> Result<(), String> {
+    match File::open(&Path::new(path)).and_then(|mut file| file.read_to_string()) {
+        Ok(text) => { keymap.load_into(text.as_slice()) },
+        Err(err) => { Err(format!("{}", err)) }
+    }
+}
+
+/// Reports a bad command line
+fn usage_error(msg : String) {
+    println!("csg: {}", msg);
+    println!("{}", brief("csg"));
+    println!("Try `csg --help` for more options.");
+    std::os::set_exit_status(EX_USAGE);
+}
+
+/// Reports an error, exiting with its primary SQLite result code
+fn fail(err : SqliteError) {
+    println!("{}", err);
+    std::os::set_exit_status(err.primary_code() as isize);
 }
\ No newline at end of file
@@ -0,0 +1,406 @@
+/// Page-at-a-time reading of large tables
+
+use std::cmp;
+use sqlite::{Sqlite, SqliteError, Statement, Value, RowIdentity, quote_identifier};
+
+/// Rows fetched beyond each end of the requested range
+pub static PAGE_MARGIN : usize = 64;
+
+/// Reads a table a page at a time. Pages next to the ones already held are
+/// found by seeking on the rowid or primary key, so they cost the same however
+/// deep into the table they are, and only a bounded window of rows is kept.
+#[derive(Clone)]
+pub struct TablePager {
+    sqlite : Sqlite,
+    /// Quoted name of the table
+    table : String,
+    /// How rows are identified, which is also the order they are paged in
+    identity : RowIdentity,
+    /// Names of the columns of each row
+    col_names : Vec<String>,
+    /// Positions of the key columns within each row
+    key_cols : Vec<usize>,
+    /// Number of rows in the table, counted when the pager is made
+    num_rows : usize,
+    /// Index of the first cached row
+    first : usize,
+    /// Cached rows, in key order
+    rows : Vec<Vec<Value>>
+}
+
+impl TablePager {
+    /// Constructor, nothing is fetched until `fetch` is called
+    pub fn new(sqlite : Sqlite, table : &str) -> Result<TablePager, SqliteError> {
+        let identity = match sqlite.row_identity(table) {
+            Ok(identity) => { identity },
+            Err(err) => { return Err(err); }
+        };
+        let quoted = quote_identifier(table);
+        let col_names = match sqlite.prepare(format!("{} {};", select_clause(&identity), quoted).as_slice()) {
+            Ok(stmt) => { stmt.col_names() },
+            Err(err) => { return Err(err); }
+        };
+        let key_cols = match identity {
+            RowIdentity::RowId(_) => { vec![0] },
+            RowIdentity::PrimaryKey(ref keys) => {
+                keys.iter().filter_map(|key| col_names.iter().position(|name| name == key)).collect()
+            },
+            RowIdentity::Nothing => { Vec::new() }
+        };
+        let mut pager = TablePager {
+            sqlite : sqlite,
+            table : quoted,
+            identity : identity,
+            col_names : col_names,
+            key_cols : key_cols,
+            num_rows : 0,
+            first : 0,
+            rows : Vec::new()
+        };
+        match pager.reload() {
+            Ok(_) => { Ok(pager) },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Names of the columns of each row
+    pub fn col_names(&self) -> &Vec<String> {
+        &self.col_names
+    }
+
+    /// How rows are identified
+    pub fn identity(&self) -> &RowIdentity {
+        &self.identity
+    }
+
+    /// Number of rows in the table
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Row at `row` if it is cached
+    pub fn get(&self, row : usize) -> Option<&Vec<Value>> {
+        if row < self.first {
+            return None;
+        }
+        self.rows.get(row - self.first)
+    }
+
+    /// Forgets every cached row and counts the table again, for use after
+    /// the table has changed
+    pub fn reload(&mut self) -> Result<(), SqliteError> {
+        let count = match self.sqlite.exec(format!("select count(*) from {};", self.table).as_slice()) {
+            Ok(results) => { results },
+            Err(err) => { return Err(err); }
+        };
+        self.num_rows = match count.rows.get(0).map(|row| &row[0]) {
+            Some(&Value::Integer(n)) => { n as usize },
+            _ => { 0 }
+        };
+        self.first = 0;
+        self.rows = Vec::new();
+        Ok(())
+    }
+
+    /// Sets column `col` of the cached row `row` to `value`, then reads the
+    /// row back so the cache shows what SQLite stored
+    pub fn update(&mut self, row : usize, col : usize, value : &Value) -> Result<(), SqliteError> {
+        let idx = match self.get(row) {
+            Some(_) => { row - self.first },
+            None => {
+                return Err(SqliteError::misuse(format!("row {} is not loaded", row + 1), None));
+            }
+        };
+        if self.key_cols.is_empty() {
+            return Err(SqliteError::misuse("rows of this table can't be told apart".to_string(), None));
+        }
+        let key = self.key_of(idx);
+        let sql = format!("update {} set {} = ? where ({}) = ({});",
+                          self.table, quote_identifier(self.col_names[col].as_slice()),
+                          self.key_list(), self.key_params());
+        let mut stmt = match self.sqlite.prepare(sql.as_slice()) {
+            Ok(stmt) => { stmt },
+            Err(err) => { return Err(err); }
+        };
+        match stmt.bind(1, value) {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+        for (i, key_value) in key.iter().enumerate() {
+            match stmt.bind(i + 2, key_value) {
+                Ok(_) => { },
+                Err(err) => { return Err(err); }
+            };
+        }
+        match stmt.execute() {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+
+        // Changing a key column can move the row anywhere in the table
+        if self.key_cols.contains(&col) {
+            return self.reload();
+        }
+        let sql = format!("{} {} where ({}) = ({});",
+                          select_clause(&self.identity), self.table, self.key_list(), self.key_params());
+        let mut stmt = match self.sqlite.prepare(sql.as_slice()) {
+            Ok(stmt) => { stmt },
+            Err(err) => { return Err(err); }
+        };
+        for (i, key_value) in key.iter().enumerate() {
+            match stmt.bind(i + 1, key_value) {
+                Ok(_) => { },
+                Err(err) => { return Err(err); }
+            };
+        }
+        let mut rows = match stmt.query() {
+            Ok(results) => { results.rows },
+            Err(err) => { return Err(err); }
+        };
+        match rows.pop() {
+            Some(updated) => {
+                self.rows[idx] = updated;
+                Ok(())
+            },
+            None => { self.reload() }
+        }
+    }
+
+    /// Adds a row with `values` for the named columns, columns left out get
+    /// their defaults
+    pub fn insert(&mut self, columns : &[String], values : &[Value]) -> Result<(), SqliteError> {
+        let sql = match columns.is_empty() {
+            true => { format!("insert into {} default values;", self.table) },
+            false => {
+                format!("insert into {} ({}) values ({});", self.table,
+                        columns.iter().map(|name| quote_identifier(name.as_slice()))
+                            .collect::<Vec<String>>().connect(", "),
+                        columns.iter().map(|_| "?".to_string()).collect::<Vec<String>>().connect(", "))
+            }
+        };
+        let mut stmt = match self.sqlite.prepare(sql.as_slice()) {
+            Ok(stmt) => { stmt },
+            Err(err) => { return Err(err); }
+        };
+        for (i, value) in values.iter().enumerate() {
+            match stmt.bind(i + 1, value) {
+                Ok(_) => { },
+                Err(err) => { return Err(err); }
+            };
+        }
+        match stmt.execute() {
+            Ok(_) => { self.reload() },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Deletes the cached row `row`
+    pub fn delete(&mut self, row : usize) -> Result<(), SqliteError> {
+        let idx = match self.get(row) {
+            Some(_) => { row - self.first },
+            None => {
+                return Err(SqliteError::misuse(format!("row {} is not loaded", row + 1), None));
+            }
+        };
+        if self.key_cols.is_empty() {
+            return Err(SqliteError::misuse("rows of this table can't be told apart".to_string(), None));
+        }
+        let key = self.key_of(idx);
+        let sql = format!("delete from {} where ({}) = ({});", self.table, self.key_list(), self.key_params());
+        let mut stmt = match self.sqlite.prepare(sql.as_slice()) {
+            Ok(stmt) => { stmt },
+            Err(err) => { return Err(err); }
+        };
+        for (i, key_value) in key.iter().enumerate() {
+            match stmt.bind(i + 1, key_value) {
+                Ok(_) => { },
+                Err(err) => { return Err(err); }
+            };
+        }
+        match stmt.execute() {
+            Ok(_) => { self.reload() },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Prepares a statement stepping through every row in the order they
+    /// are paged in, for reading the whole table without holding it. The
+    /// rowid is left out.
+    pub fn select_all(&self) -> Result<Statement, SqliteError> {
+        self.sqlite.prepare(format!("select * from {}{};", self.table, self.order_by_clause(false)).as_slice())
+    }
+
+    /// Makes sure rows `start` to `start + count` are cached, along with
+    /// `PAGE_MARGIN` rows either side
+    pub fn fetch(&mut self, start : usize, count : usize) -> Result<(), SqliteError> {
+        let end = cmp::min(start + count, self.num_rows);
+        let cached_end = self.first + self.rows.len();
+        if start >= end || (start >= self.first && end <= cached_end) {
+            return Ok(());
+        }
+        let want_first = if start > PAGE_MARGIN { start - PAGE_MARGIN } else { 0 };
+        let want_end = cmp::min(end + PAGE_MARGIN, self.num_rows);
+        let can_seek = !self.key_cols.is_empty() && !self.rows.is_empty();
+
+        let result = if can_seek && start >= self.first && start <= cached_end {
+            self.extend_forward(want_end - cached_end)
+        } else if can_seek && start < self.first && end >= self.first {
+            self.extend_backward(self.first - want_first)
+        } else {
+            self.load(want_first, want_end - want_first)
+        };
+        match result {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+        self.trim(start, end);
+        Ok(())
+    }
+
+    /// Appends `count` rows following the last cached one
+    fn extend_forward(&mut self, count : usize) -> Result<(), SqliteError> {
+        let key = self.key_of(self.rows.len() - 1);
+        let sql = format!("{} {} where ({}) > ({}) order by {} limit ?;",
+                          select_clause(&self.identity), self.table,
+                          self.key_list(), self.key_params(), self.order_by(false));
+        match self.query(sql.as_slice(), key, count) {
+            Ok(rows) => {
+                self.rows.extend(rows.into_iter());
+                Ok(())
+            },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Prepends `count` rows preceding the first cached one
+    fn extend_backward(&mut self, count : usize) -> Result<(), SqliteError> {
+        let key = self.key_of(0);
+        let sql = format!("{} {} where ({}) < ({}) order by {} limit ?;",
+                          select_clause(&self.identity), self.table,
+                          self.key_list(), self.key_params(), self.order_by(true));
+        match self.query(sql.as_slice(), key, count) {
+            Ok(mut rows) => {
+                rows.reverse();
+                self.first = self.first - rows.len();
+                rows.extend(self.rows.drain());
+                self.rows = rows;
+                Ok(())
+            },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Replaces the cache with `count` rows starting at `first`. The end of
+    /// the table is read backwards through the key order, anywhere else
+    /// needs an offset.
+    fn load(&mut self, first : usize, count : usize) -> Result<(), SqliteError> {
+        let select = select_clause(&self.identity);
+        if first > 0 && first + count >= self.num_rows && !self.key_cols.is_empty() {
+            let sql = format!("{} {}{} limit ?;", select, self.table, self.order_by_clause(true));
+            return match self.query(sql.as_slice(), Vec::new(), count) {
+                Ok(mut rows) => {
+                    rows.reverse();
+                    // Comes up short if rows were deleted since counting
+                    self.first = if rows.len() < self.num_rows { self.num_rows - rows.len() } else { 0 };
+                    self.rows = rows;
+                    Ok(())
+                },
+                Err(err) => { Err(err) }
+            };
+        }
+        let sql = format!("{} {}{} limit ? offset {};", select, self.table, self.order_by_clause(false), first);
+        match self.query(sql.as_slice(), Vec::new(), count) {
+            Ok(rows) => {
+                self.first = first;
+                self.rows = rows;
+                Ok(())
+            },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Drops cached rows so no more than the requested range and a margin of
+    /// twice `PAGE_MARGIN` either side are kept
+    fn trim(&mut self, start : usize, end : usize) {
+        let keep_first = if start > 2 * PAGE_MARGIN { start - 2 * PAGE_MARGIN } else { 0 };
+        let keep_end = end + 2 * PAGE_MARGIN;
+        if self.first + self.rows.len() > keep_end && keep_end > self.first {
+            self.rows.truncate(keep_end - self.first);
+        }
+        if self.first < keep_first {
+            let drop_count = cmp::min(keep_first - self.first, self.rows.len());
+            let kept : Vec<Vec<Value>> = self.rows.drain().skip(drop_count).collect();
+            self.rows = kept;
+            self.first = self.first + drop_count;
+        }
+    }
+
+    /// Runs a page query, binding `key` and then the row limit
+    fn query(&self, sql : &str, key : Vec<Value>, limit : usize) -> Result<Vec<Vec<Value>>, SqliteError> {
+        let mut stmt : Statement = match self.sqlite.prepare(sql) {
+            Ok(stmt) => { stmt },
+            Err(err) => { return Err(err); }
+        };
+        for (i, value) in key.iter().enumerate() {
+            match stmt.bind(i + 1, value) {
+                Ok(_) => { },
+                Err(err) => { return Err(err); }
+            };
+        }
+        match stmt.bind_int(key.len() + 1, limit as i64) {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+        stmt.query().map(|results| results.rows)
+    }
+
+    /// Key values of the cached row at `idx`
+    fn key_of(&self, idx : usize) -> Vec<Value> {
+        self.key_cols.iter().map(|&col| self.rows[idx][col].clone()).collect()
+    }
+
+    /// Key columns as SQL, separated by commas
+    fn key_list(&self) -> String {
+        match self.identity {
+            RowIdentity::PrimaryKey(ref keys) => {
+                keys.iter().map(|key| quote_identifier(key.as_slice())).collect::<Vec<String>>().connect(", ")
+            },
+            RowIdentity::RowId(ref alias) => { alias.clone() },
+            RowIdentity::Nothing => { "".to_string() }
+        }
+    }
+
+    /// One placeholder per key column
+    fn key_params(&self) -> String {
+        self.key_cols.iter().map(|_| "?".to_string()).collect::<Vec<String>>().connect(", ")
+    }
+
+    /// Key columns to order by, optionally reversed
+    fn order_by(&self, descending : bool) -> String {
+        let direction = if descending { " desc" } else { "" };
+        match self.identity {
+            RowIdentity::PrimaryKey(ref keys) => {
+                keys.iter().map(|key| format!("{}{}", quote_identifier(key.as_slice()), direction))
+                    .collect::<Vec<String>>().connect(", ")
+            },
+            RowIdentity::RowId(ref alias) => { format!("{}{}", alias, direction) },
+            RowIdentity::Nothing => { "".to_string() }
+        }
+    }
+
+    /// An order by clause, empty when there is nothing to order by
+    fn order_by_clause(&self, descending : bool) -> String {
+        match self.key_cols.is_empty() {
+            true => { "".to_string() },
+            false => { format!(" order by {}", self.order_by(descending)) }
+        }
+    }
+}
+
+/// Start of a query selecting whole rows, led by the rowid when there is one
+pub fn select_clause(identity : &RowIdentity) -> String {
+    match *identity {
+        RowIdentity::RowId(ref alias) => format!("select {},* from", alias),
+        _ => "select * from".to_string()
+    }
+}
\ No newline at end of file
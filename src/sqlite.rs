@@ -1,81 +1,461 @@
 /// Sqlite3 FFI wrapper
 extern crate libc;
 
-use sqlite::libc::{c_char, c_int, c_void};
+use sqlite::libc::{c_char, c_int, c_void, c_double};
+use std::fmt;
 use std::ptr;
-use std::sync::{Mutex};
-use cext::{cstrs_to_strs, str_to_cstr, cstr_to_str};
+use std::rc::{self, Rc};
+use std::ascii::AsciiExt;
+use std::io::stdio::stderr;
+use cext::{str_to_cstr, cstr_to_str, bytes_to_vec};
 
-static LIST_TABLES_QUERY : &'static str = "select name from sqlite_master where type = 'table';";
+static LIST_TABLES_QUERY : &'static str = "select name, type from sqlite_master where type = 'table' or type = 'view';";
+static LIST_SCHEMA_QUERY : &'static str = "select name, type, tbl_name from sqlite_master \
+    where type in ('table', 'view', 'index', 'trigger') \
+    order by case type when 'table' then 0 when 'view' then 1 when 'index' then 2 else 3 end, name;";
+static SCHEMA_PROBE_QUERY : &'static str = "select count(*) from sqlite_master;";
+static OBJECT_TYPE_QUERY : &'static str = "select type from sqlite_master where name = ?;";
+static OBJECT_SQL_QUERY : &'static str = "select sql from sqlite_master where name = ?;";
+static TRIGGERS_QUERY : &'static str = "select name, sql from sqlite_master where type = 'trigger' and tbl_name = ? order by name;";
 // XXX: Apparently format strings have to be literals?
 // static DUMP_TABLE_QUERY : &'static str = "select * from {}";
 
-// Lazy initialization of global execution state, thx Jesse
-// It's thread-safe too, which doesn't matter in the current
-// iteration of this software but may matter later
-lazy_static! {
-    pub static ref exec_results: Mutex<ExecResult> = Mutex::new(ExecResult::new());
-}
-
 // XXX: This is unused, but may need to be enforced
 // later for proper table formatting
 pub static MAX_TABLE_NAME_LENGTH : usize = 128;
 
+// Names the rowid goes by, unless a column has taken them
+static ROWID_ALIASES : [&'static str; 3] = ["rowid", "_rowid_", "oid"];
+
+// Bytes of a BLOB shown in hex in its cell
+static BLOB_PREVIEW : usize = 8;
+
+// Result codes
+const SQLITE_OK : c_int = 0;
+const SQLITE_MISUSE : c_int = 21;
+const SQLITE_RANGE : c_int = 25;
+const SQLITE_ROW : c_int = 100;
+const SQLITE_DONE : c_int = 101;
+
+// Fundamental datatypes
+const SQLITE_INTEGER : c_int = 1;
+const SQLITE_FLOAT : c_int = 2;
+const SQLITE_TEXT : c_int = 3;
+const SQLITE_BLOB : c_int = 4;
+const SQLITE_NULL : c_int = 5;
+
+// Flags for sqlite3_open_v2
+const SQLITE_OPEN_READONLY : c_int = 0x01;
+const SQLITE_OPEN_READWRITE : c_int = 0x02;
+const SQLITE_OPEN_CREATE : c_int = 0x04;
+const SQLITE_OPEN_URI : c_int = 0x40;
+
+// Tells SQLite to make its own copy of bound text and blobs
+const SQLITE_TRANSIENT : isize = -1;
+
 /** Wrapper for opaque struct */
 #[repr(C)]
 pub struct c_sqlite3;
 
+/** Wrapper for opaque struct */
+#[repr(C)]
+pub struct c_sqlite3_stmt;
+
 #[link(name="sqlite3")]
 extern {
-    fn sqlite3_open(filename: *const c_char,        
-                    db_handle: *const *const c_sqlite3) -> c_int;
-    fn sqlite3_exec(db_handle: *const c_sqlite3, 
-                    sql: *const c_char,
-                    cb: extern fn(*const c_void, 
-                                  c_int, 
-                                  *const *const c_char, 
-                                  *const *const c_char) -> c_int,
-                    arg: *const c_void,
-                    errmsg: *mut *const c_char) -> c_int;
-}
-
-/// Callback method used for exec
-extern fn exec_cb(_ : *const c_void, 
-                  num_cols : c_int, 
-                  col_text : *const *const c_char, 
-                  col_names : *const *const c_char) -> c_int {
-    let num_cols = num_cols as usize;
-    let col_text = cstrs_to_strs(col_text, num_cols);
-    let col_names = cstrs_to_strs(col_names, num_cols);
-    let mut results = exec_results.lock().unwrap();
-
-    let need_num_cols = match results.num_cols {
-        Some(_) => false,
-        None => true
-    };
-    if need_num_cols {
-        results.num_cols = Some(num_cols);
+    fn sqlite3_open_v2(filename: *const c_char,
+                       db_handle: *const *const c_sqlite3,
+                       flags: c_int,
+                       vfs: *const c_char) -> c_int;
+    fn sqlite3_close(db_handle: *const c_sqlite3) -> c_int;
+    fn sqlite3_extended_result_codes(db_handle: *const c_sqlite3, onoff: c_int) -> c_int;
+    fn sqlite3_extended_errcode(db_handle: *const c_sqlite3) -> c_int;
+    fn sqlite3_errstr(code: c_int) -> *const c_char;
+    fn sqlite3_errmsg(db_handle: *const c_sqlite3) -> *const c_char;
+    fn sqlite3_changes(db_handle: *const c_sqlite3) -> c_int;
+    fn sqlite3_total_changes(db_handle: *const c_sqlite3) -> c_int;
+    fn sqlite3_db_readonly(db_handle: *const c_sqlite3, db_name: *const c_char) -> c_int;
+
+    // Prepared statements
+    fn sqlite3_prepare_v2(db_handle: *const c_sqlite3,
+                          sql: *const c_char,
+                          nbyte: c_int,
+                          stmt: *mut *const c_sqlite3_stmt,
+                          tail: *mut *const c_char) -> c_int;
+    fn sqlite3_step(stmt: *const c_sqlite3_stmt) -> c_int;
+    fn sqlite3_reset(stmt: *const c_sqlite3_stmt) -> c_int;
+    fn sqlite3_finalize(stmt: *const c_sqlite3_stmt) -> c_int;
+    fn sqlite3_sql(stmt: *const c_sqlite3_stmt) -> *const c_char;
+    fn sqlite3_complete(sql: *const c_char) -> c_int;
+
+    // Parameter binding
+    fn sqlite3_bind_int64(stmt: *const c_sqlite3_stmt, idx: c_int, val: i64) -> c_int;
+    fn sqlite3_bind_double(stmt: *const c_sqlite3_stmt, idx: c_int, val: c_double) -> c_int;
+    fn sqlite3_bind_text(stmt: *const c_sqlite3_stmt,
+                         idx: c_int,
+                         val: *const c_char,
+                         nbyte: c_int,
+                         destructor: isize) -> c_int;
+    fn sqlite3_bind_blob(stmt: *const c_sqlite3_stmt,
+                         idx: c_int,
+                         val: *const c_void,
+                         nbyte: c_int,
+                         destructor: isize) -> c_int;
+    fn sqlite3_bind_null(stmt: *const c_sqlite3_stmt, idx: c_int) -> c_int;
+    fn sqlite3_bind_parameter_index(stmt: *const c_sqlite3_stmt, name: *const c_char) -> c_int;
+    fn sqlite3_clear_bindings(stmt: *const c_sqlite3_stmt) -> c_int;
+
+    // Result columns
+    fn sqlite3_column_count(stmt: *const c_sqlite3_stmt) -> c_int;
+    fn sqlite3_column_name(stmt: *const c_sqlite3_stmt, col: c_int) -> *const c_char;
+    fn sqlite3_column_type(stmt: *const c_sqlite3_stmt, col: c_int) -> c_int;
+    fn sqlite3_column_int64(stmt: *const c_sqlite3_stmt, col: c_int) -> i64;
+    fn sqlite3_column_double(stmt: *const c_sqlite3_stmt, col: c_int) -> c_double;
+    fn sqlite3_column_text(stmt: *const c_sqlite3_stmt, col: c_int) -> *const u8;
+    fn sqlite3_column_blob(stmt: *const c_sqlite3_stmt, col: c_int) -> *const u8;
+    fn sqlite3_column_bytes(stmt: *const c_sqlite3_stmt, col: c_int) -> c_int;
+}
+
+/// Primary SQLite result codes, see https://sqlite.org/rescode.html
+#[derive(Clone, PartialEq, Show)]
+pub enum ErrorCode {
+    Error,
+    Internal,
+    Perm,
+    Abort,
+    Busy,
+    Locked,
+    NoMem,
+    ReadOnly,
+    Interrupt,
+    IoErr,
+    Corrupt,
+    NotFound,
+    Full,
+    CantOpen,
+    Protocol,
+    Empty,
+    Schema,
+    TooBig,
+    Constraint,
+    Mismatch,
+    Misuse,
+    NoLfs,
+    Auth,
+    Format,
+    Range,
+    NotADb,
+    Unknown(c_int)
+}
+
+impl ErrorCode {
+    /// Maps a primary or extended result code to its primary code
+    pub fn from_code(code : c_int) -> ErrorCode {
+        match code & 0xff {
+            1 => ErrorCode::Error,
+            2 => ErrorCode::Internal,
+            3 => ErrorCode::Perm,
+            4 => ErrorCode::Abort,
+            5 => ErrorCode::Busy,
+            6 => ErrorCode::Locked,
+            7 => ErrorCode::NoMem,
+            8 => ErrorCode::ReadOnly,
+            9 => ErrorCode::Interrupt,
+            10 => ErrorCode::IoErr,
+            11 => ErrorCode::Corrupt,
+            12 => ErrorCode::NotFound,
+            13 => ErrorCode::Full,
+            14 => ErrorCode::CantOpen,
+            15 => ErrorCode::Protocol,
+            16 => ErrorCode::Empty,
+            17 => ErrorCode::Schema,
+            18 => ErrorCode::TooBig,
+            19 => ErrorCode::Constraint,
+            20 => ErrorCode::Mismatch,
+            21 => ErrorCode::Misuse,
+            22 => ErrorCode::NoLfs,
+            23 => ErrorCode::Auth,
+            24 => ErrorCode::Format,
+            25 => ErrorCode::Range,
+            26 => ErrorCode::NotADb,
+            _ => ErrorCode::Unknown(code & 0xff)
+        }
+    }
+
+    /// Name of the code as it appears in sqlite3.h
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ErrorCode::Error => "SQLITE_ERROR",
+            ErrorCode::Internal => "SQLITE_INTERNAL",
+            ErrorCode::Perm => "SQLITE_PERM",
+            ErrorCode::Abort => "SQLITE_ABORT",
+            ErrorCode::Busy => "SQLITE_BUSY",
+            ErrorCode::Locked => "SQLITE_LOCKED",
+            ErrorCode::NoMem => "SQLITE_NOMEM",
+            ErrorCode::ReadOnly => "SQLITE_READONLY",
+            ErrorCode::Interrupt => "SQLITE_INTERRUPT",
+            ErrorCode::IoErr => "SQLITE_IOERR",
+            ErrorCode::Corrupt => "SQLITE_CORRUPT",
+            ErrorCode::NotFound => "SQLITE_NOTFOUND",
+            ErrorCode::Full => "SQLITE_FULL",
+            ErrorCode::CantOpen => "SQLITE_CANTOPEN",
+            ErrorCode::Protocol => "SQLITE_PROTOCOL",
+            ErrorCode::Empty => "SQLITE_EMPTY",
+            ErrorCode::Schema => "SQLITE_SCHEMA",
+            ErrorCode::TooBig => "SQLITE_TOOBIG",
+            ErrorCode::Constraint => "SQLITE_CONSTRAINT",
+            ErrorCode::Mismatch => "SQLITE_MISMATCH",
+            ErrorCode::Misuse => "SQLITE_MISUSE",
+            ErrorCode::NoLfs => "SQLITE_NOLFS",
+            ErrorCode::Auth => "SQLITE_AUTH",
+            ErrorCode::Format => "SQLITE_FORMAT",
+            ErrorCode::Range => "SQLITE_RANGE",
+            ErrorCode::NotADb => "SQLITE_NOTADB",
+            ErrorCode::Unknown(_) => "SQLITE_UNKNOWN"
+        }
+    }
+}
+
+/// An error reported by SQLite
+#[derive(Clone, Show)]
+pub struct SqliteError {
+    /// Primary result code
+    pub code : ErrorCode,
+    /// Extended result code, equal to the primary code when there is none
+    pub extended_code : c_int,
+    /// Text from sqlite3_errmsg
+    pub message : String,
+    /// The SQL that failed, if any
+    pub sql : Option<String>
+}
+
+impl SqliteError {
+    pub fn new(extended_code : c_int, message : String, sql : Option<String>) -> SqliteError {
+        SqliteError {
+            code : ErrorCode::from_code(extended_code),
+            extended_code : extended_code,
+            message : message,
+            sql : sql
+        }
+    }
+
+    /// An error made by the caller rather than reported by SQLite
+    pub fn misuse(message : String, sql : Option<String>) -> SqliteError {
+        SqliteError::new(SQLITE_MISUSE, message, sql)
+    }
+
+    /// Primary result code as a number, suitable for an exit status
+    pub fn primary_code(&self) -> c_int {
+        self.extended_code & 0xff
+    }
+}
+
+impl fmt::String for SqliteError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self.sql {
+            Some(ref sql) => write!(f, "{}: {} (extended code {}) in: {}",
+                                    self.code.name(), self.message, self.extended_code, sql),
+            None => write!(f, "{}: {} (extended code {})",
+                           self.code.name(), self.message, self.extended_code)
+        }
+    }
+}
+
+/// Quotes a schema name so it can be interpolated into SQL as an identifier,
+/// any embedded double quotes are doubled
+pub fn quote_identifier(name : &str) -> String {
+    format!("\"{}\"", name.replace("\"", "\"\""))
+}
+
+/// How a database is opened, filenames starting with `file:` are always
+/// treated as URIs so options like `?immutable=1` can be passed
+#[derive(Clone, Copy, PartialEq, Show)]
+pub enum OpenMode {
+    /// Never write to the database
+    ReadOnly,
+    /// Read and write a database that must already exist
+    ReadWrite,
+    /// Read and write, creating the database if needed
+    Create
+}
+
+impl OpenMode {
+    /// Flags passed to sqlite3_open_v2
+    fn flags(&self) -> c_int {
+        SQLITE_OPEN_URI | match *self {
+            OpenMode::ReadOnly => SQLITE_OPEN_READONLY,
+            OpenMode::ReadWrite => SQLITE_OPEN_READWRITE,
+            OpenMode::Create => SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE
+        }
+    }
+}
+
+/// Kinds of schema objects that can be browsed
+#[derive(Clone, PartialEq, Show)]
+pub enum ObjectKind {
+    Table,
+    View,
+    Index,
+    Trigger
+}
+
+impl ObjectKind {
+    /// Kind as sqlite_master spells it
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ObjectKind::Table => "table",
+            ObjectKind::View => "view",
+            ObjectKind::Index => "index",
+            ObjectKind::Trigger => "trigger"
+        }
+    }
+
+    /// Kind from its sqlite_master spelling
+    pub fn from_name(name : &str) -> ObjectKind {
+        match name {
+            "view" => ObjectKind::View,
+            "index" => ObjectKind::Index,
+            "trigger" => ObjectKind::Trigger,
+            _ => ObjectKind::Table
+        }
+    }
+}
+
+/// A named object from sqlite_master
+#[derive(Clone, PartialEq, Show)]
+pub struct SchemaObject {
+    pub name : String,
+    pub kind : ObjectKind,
+    /// Table an index or trigger belongs to, the object itself otherwise
+    pub table : String
+}
+
+impl SchemaObject {
+    /// Only tables can be written to
+    pub fn is_read_only(&self) -> bool {
+        self.kind != ObjectKind::Table
     }
 
-    let need_col_names = match results.col_names {
-        Some(_) => false,
-        None => true
-    };
-    if need_col_names {
-        results.col_names = Some(col_names);
+    /// Whether SQLite made the object for its own use
+    pub fn is_internal(&self) -> bool {
+        self.name.starts_with("sqlite_")
+    }
+}
+
+/// How the rows of a table dump are told apart
+#[derive(Clone, PartialEq, Show)]
+pub enum RowIdentity {
+    /// The implicit rowid, selected as the first column of the dump under
+    /// the alias no column shadows
+    RowId(String),
+    /// The primary key columns of a WITHOUT ROWID table
+    PrimaryKey(Vec<String>),
+    /// Views, and tables whose rowid every alias of is shadowed with no
+    /// primary key to fall back on, have no stable identity for their rows
+    Nothing
+}
+
+/// A column as described by `pragma table_info`
+#[derive(Clone, PartialEq, Show)]
+pub struct ColumnInfo {
+    pub name : String,
+    /// Type the column was declared with, may be empty
+    pub decl_type : String,
+    pub not_null : bool,
+    /// SQL expression of the default value, if there is one
+    pub default : Option<String>,
+    /// Position within the primary key, 0 if not part of it
+    pub pk : usize
+}
+
+/// An index as described by `pragma index_list` and `pragma index_info`
+#[derive(Clone, PartialEq, Show)]
+pub struct IndexInfo {
+    pub name : String,
+    pub unique : bool,
+    /// Indexed columns, in order. Expressions have no name and show as "?"
+    pub columns : Vec<String>
+}
+
+/// A foreign key as described by `pragma foreign_key_list`
+#[derive(Clone, PartialEq, Show)]
+pub struct ForeignKey {
+    /// Columns of this table
+    pub columns : Vec<String>,
+    /// Table referred to, and its columns. These are empty when the key
+    /// refers to the primary key.
+    pub table : String,
+    pub to : Vec<String>,
+    pub on_update : String,
+    pub on_delete : String
+}
+
+/// A single column value, tagged with its SQLite storage class
+#[derive(Clone, PartialEq, Show)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>)
+}
+
+impl Value {
+    /// Name of the storage class as SQLite spells it
+    pub fn storage_class(&self) -> &'static str {
+        match *self {
+            Value::Null => "NULL",
+            Value::Integer(_) => "INTEGER",
+            Value::Real(_) => "REAL",
+            Value::Text(_) => "TEXT",
+            Value::Blob(_) => "BLOB"
+        }
     }
 
-    results.col_text.push(col_text);
+    /// Length in bytes, as `length()` counts it for BLOBs. Numbers are
+    /// measured by their text.
+    pub fn byte_len(&self) -> usize {
+        match *self {
+            Value::Null => 0,
+            Value::Text(ref s) => s.len(),
+            Value::Blob(ref b) => b.len(),
+            _ => self.to_text().len()
+        }
+    }
 
-    0 as c_int
+    /// Text used to show the value in a cell
+    pub fn to_text(&self) -> String {
+        match *self {
+            Value::Null => "<NULL>".to_string(),
+            Value::Integer(n) => format!("{}", n),
+            Value::Real(f) => {
+                // Keep a trailing .0 so reals don't look like integers
+                if f.fract() == 0.0 && f.is_finite() {
+                    format!("{:.1}", f)
+                } else {
+                    format!("{}", f)
+                }
+            },
+            Value::Text(ref s) => s.clone(),
+            Value::Blob(ref b) => {
+                if b.is_empty() {
+                    return "<BLOB 0 bytes>".to_string();
+                }
+                // The first few bytes, enough to recognise most formats
+                let preview : Vec<String> = b.iter().take(BLOB_PREVIEW).map(|byte| format!("{:02x}", byte)).collect();
+                format!("<BLOB {} bytes> {}{}", b.len(), preview.connect(" "),
+                        if b.len() > BLOB_PREVIEW { " …" } else { "" })
+            }
+        }
+    }
 }
 
 /// Represents the result of a call to SQLite library
 pub struct ExecResult {
     /// Number of columns
     pub num_cols : Option<usize>,
-    /// Text in the columns
-    pub col_text : Vec<Vec<String>>,
+    /// Values of each row
+    pub rows : Vec<Vec<Value>>,
     /// Names of the columns
     pub col_names : Option<Vec<String>>
 }
@@ -84,7 +464,7 @@ impl ExecResult {
     pub fn new() -> ExecResult {
         ExecResult {
             num_cols : None,
-            col_text : Vec::new(),
+            rows : Vec::new(),
             col_names : None
         }
     }
@@ -92,7 +472,7 @@ impl ExecResult {
     /// Clears an execution result
     pub fn reset(&mut self) {
         self.num_cols = None;
-        self.col_text = Vec::new();
+        self.rows = Vec::new();
         self.col_names = None;
     }
 
@@ -102,62 +482,652 @@ impl ExecResult {
     }
 }
 
+/// Owns a database handle, closing it exactly once
+struct Connection {
+    /// A pointer to SQLite databse, null once closed
+    db_handle : *const c_sqlite3
+}
+
+impl Connection {
+    /// Closes the handle, it stays open if SQLite refuses
+    fn close(&mut self) -> Result<(), SqliteError> {
+        if self.db_handle.is_null() {
+            return Ok(());
+        }
+        let ret = unsafe { sqlite3_close(self.db_handle) };
+        if ret != SQLITE_OK {
+            let message = cstr_to_str(unsafe { sqlite3_errmsg(self.db_handle) });
+            return Err(SqliteError::new(ret, message, None));
+        }
+        self.db_handle = ptr::null();
+        Ok(())
+    }
+}
+
+impl Drop for Connection {
+    /// Closes the database, there is no caller left to hand an error to
+    /// so it goes to stderr
+    fn drop(&mut self) {
+        match self.close() {
+            Ok(_) => { },
+            Err(err) => { let _ = writeln!(&mut stderr(), "sqlite3_close: {}", err); }
+        }
+    }
+}
+
+/// A shared connection to a database, closed when the last clone goes away
 #[derive(Clone)]
 pub struct Sqlite {
-    /// A pointer to SQLite databse
-    db_handle : *const c_sqlite3
+    conn : Rc<Connection>
 }
 
 impl Sqlite {  
-    /// Constructor
-    pub fn new(filename : &str) -> Sqlite {
+    /// Constructor, creates the database if it doesn't exist
+    pub fn new(filename : &str) -> Result<Sqlite, SqliteError> {
+        Sqlite::new_with_mode(filename, OpenMode::Create)
+    }
+
+    /// Constructor, fails if the file can't be opened or isn't a database
+    pub fn new_with_mode(filename : &str, mode : OpenMode) -> Result<Sqlite, SqliteError> {
         let db_handle = ptr::null();
-        Sqlite::open(filename, &db_handle).ok();
-        Sqlite { db_handle: db_handle }
+        match Sqlite::open(filename, mode, &db_handle) {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+        let sqlite = Sqlite { conn : Rc::new(Connection { db_handle : db_handle }) };
+
+        // Opening is lazy, reading the schema makes SQLite look at the file
+        match sqlite.prepare(SCHEMA_PROBE_QUERY) {
+            Ok(_) => { Ok(sqlite) },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Gives up this reference to the connection, closing the database if it
+    /// was the last one
+    pub fn close(self) -> Result<(), SqliteError> {
+        match rc::try_unwrap(self.conn) {
+            Ok(mut conn) => { conn.close() },
+            Err(_) => { Ok(()) }
+        }
+    }
+
+    /// The raw database handle
+    fn handle(&self) -> *const c_sqlite3 {
+        self.conn.db_handle
     }
 
     /// Open a new database
-    pub fn open(filename : &str, db_handle : & *const c_sqlite3) -> Result<(), String> {
-        let filename = str_to_cstr(filename);
-        let ret = unsafe { sqlite3_open(filename.as_ptr(), db_handle as *const *const c_sqlite3) };
-        match ret {
-            0 => { Ok(()) }
-            _ => { Err(format!("sqlite3_open: got error code: {}", ret)) }
+    pub fn open(filename : &str, mode : OpenMode, db_handle : & *const c_sqlite3) -> Result<(), SqliteError> {
+        let cfilename = str_to_cstr(filename);
+        let ret = unsafe {
+            sqlite3_open_v2(cfilename.as_ptr(),
+                            db_handle as *const *const c_sqlite3,
+                            mode.flags(),
+                            ptr::null())
+        };
+        if ret == SQLITE_OK {
+            unsafe { sqlite3_extended_result_codes(*db_handle, 1); }
+            return Ok(());
+        }
+        // A handle is usually still allocated and holds the message
+        let err = match db_handle.is_null() {
+            true => {
+                SqliteError::new(ret, cstr_to_str(unsafe { sqlite3_errstr(ret) }), None)
+            },
+            false => {
+                let err = SqliteError::new(ret, cstr_to_str(unsafe { sqlite3_errmsg(*db_handle) }), None);
+                unsafe { sqlite3_close(*db_handle); }
+                err
+            }
+        };
+        Err(SqliteError { message : format!("{}: {}", err.message, filename), .. err })
+    }
+
+    /// Execute every statement in `sql`, returning the rows produced
+    /// by the last one
+    pub fn exec(&self, sql : &str) -> Result<ExecResult, SqliteError> {
+        let sql = str_to_cstr(sql);
+        let mut next : *const c_char = sql.as_ptr();
+        let mut results = ExecResult::new();
+        loop {
+            let mut tail : *const c_char = ptr::null();
+            let mut stmt = match self.prepare_at(next, &mut tail) {
+                Ok(Some(stmt)) => { stmt },
+                // Nothing but whitespace or comments left
+                Ok(None) => { break; },
+                Err(err) => { return Err(err); }
+            };
+            results = match stmt.query() {
+                Ok(results) => { results },
+                Err(err) => { return Err(err); }
+            };
+            next = tail;
         }
+        Ok(results)
     }
 
-    /// Execute a command from the SQLite code
-    pub fn exec(&self,
-                sql: &str,
-                cb: extern fn(*const c_void, 
-                              c_int, 
-                              *const *const c_char, 
-                              *const *const c_char) -> c_int) -> Result<(), String> {
+    /// Compiles a single SQL statement
+    pub fn prepare(&self, sql : &str) -> Result<Statement, SqliteError> {
         let sql = str_to_cstr(sql);
-        let mut errmsg = ptr::null();
-        exec_results.lock().unwrap().reset();
-        let _ = unsafe { 
-            sqlite3_exec(self.db_handle, 
-                         sql.as_ptr(), 
-                         cb,
-                         ptr::null(),
-                         &mut errmsg as *mut *const c_char)
+        let mut tail : *const c_char = ptr::null();
+        match self.prepare_at(sql.as_ptr(), &mut tail) {
+            Ok(Some(stmt)) => { Ok(stmt) },
+            Ok(None) => {
+                Err(SqliteError::new(SQLITE_MISUSE,
+                                     "no statement to prepare".to_string(),
+                                     Some(cstr_to_str(sql.as_ptr()))))
+            },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Compiles the first statement at `sql`, pointing `tail` past it
+    fn prepare_at(&self, sql : *const c_char, tail : &mut *const c_char) -> Result<Option<Statement>, SqliteError> {
+        let mut stmt = ptr::null();
+        let ret = unsafe {
+            sqlite3_prepare_v2(self.handle(),
+                               sql,
+                               -1,
+                               &mut stmt as *mut *const c_sqlite3_stmt,
+                               tail as *mut *const c_char)
         };
+        if ret != SQLITE_OK {
+            // SQLite stops reading at the error, or at the end of the
+            // statement when it wasn't a syntax error
+            let read = match tail.is_null() {
+                true => 0,
+                false => *tail as usize - sql as usize
+            };
+            return Err(self.error(Some(statement_at(cstr_to_str(sql), read))));
+        }
+        if stmt.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(Statement {
+            sqlite : self.clone(),
+            stmt : stmt,
+            sql : cstr_to_str(unsafe { sqlite3_sql(stmt) })
+        }))
+    }
+
+    /// Number of rows changed by the most recent insert, update or delete
+    pub fn changes(&self) -> usize {
+        unsafe { sqlite3_changes(self.handle()) as usize }
+    }
+
+    /// Number of rows changed since the database was opened
+    pub fn total_changes(&self) -> usize {
+        unsafe { sqlite3_total_changes(self.handle()) as usize }
+    }
+
+    /// Whether writes to the main database will be refused, either because
+    /// it was opened read-only or because the file can't be written
+    pub fn is_read_only(&self) -> bool {
+        let main = str_to_cstr("main");
+        unsafe { sqlite3_db_readonly(self.handle(), main.as_ptr()) == 1 }
+    }
+
+    /// Most recent error reported for this connection
+    fn error(&self, sql : Option<String>) -> SqliteError {
+        let code = unsafe { sqlite3_extended_errcode(self.handle()) };
+        let message = cstr_to_str(unsafe { sqlite3_errmsg(self.handle()) });
+        SqliteError::new(code, message, sql)
+    }
 
-        if !errmsg.is_null() {
-            let errmsg = cstr_to_str(errmsg);
-            return Err(errmsg);
+    /// Calls SQLite to list all tables and views, as (name, type) rows
+    pub fn list_tables(&self) -> Result<ExecResult, SqliteError> {
+        self.exec(LIST_TABLES_QUERY)
+    }
+
+    /// Like `list_tables`, but as schema objects
+    pub fn list_objects(&self) -> Result<Vec<SchemaObject>, SqliteError> {
+        match self.list_tables() {
+            Ok(results) => {
+                Ok(results.rows.iter().map(|row| {
+                    SchemaObject {
+                        name : row[0].to_text(),
+                        kind : ObjectKind::from_name(row[1].to_text().as_slice()),
+                        table : row[0].to_text()
+                    }
+                }).collect())
+            },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Lists tables, views, indexes and triggers, grouped in that order
+    pub fn list_schema(&self) -> Result<Vec<SchemaObject>, SqliteError> {
+        match self.exec(LIST_SCHEMA_QUERY) {
+            Ok(results) => {
+                Ok(results.rows.iter().map(|row| {
+                    SchemaObject {
+                        name : row[0].to_text(),
+                        kind : ObjectKind::from_name(row[1].to_text().as_slice()),
+                        table : row[2].to_text()
+                    }
+                }).collect())
+            },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Counts the rows of `table`
+    pub fn count_rows(&self, table : &str) -> Result<usize, SqliteError> {
+        match self.exec(format!("select count(*) from {};", quote_identifier(table)).as_slice()) {
+            Ok(results) => {
+                Ok(match results.rows.get(0).map(|row| &row[0]) {
+                    Some(&Value::Integer(n)) => n as usize,
+                    _ => 0
+                })
+            },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// Works out how rows of `table` can be identified
+    pub fn row_identity(&self, table : &str) -> Result<RowIdentity, SqliteError> {
+        let mut stmt = match self.prepare(OBJECT_TYPE_QUERY) {
+            Ok(stmt) => { stmt },
+            Err(err) => { return Err(err); }
+        };
+        match stmt.bind_text(1, table) {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+        let kind = match stmt.query() {
+            Ok(results) => { results.rows.get(0).map(|row| row[0].to_text()) },
+            Err(err) => { return Err(err); }
+        };
+        if kind == Some("view".to_string()) {
+            return Ok(RowIdentity::Nothing);
+        }
+
+        let info = match self.table_info(table) {
+            Ok(info) => { info },
+            Err(err) => { return Err(err); }
+        };
+        // A column named like the rowid hides it, so only a free alias can
+        // be trusted to mean the rowid. WITHOUT ROWID tables refuse to
+        // compile a reference to one.
+        let alias = ROWID_ALIASES.iter().find(|alias| {
+            !info.iter().any(|column| column.name.as_slice().eq_ignore_ascii_case(**alias))
+        });
+        match alias {
+            Some(alias) => {
+                let probe = format!("select {} from {};", alias, quote_identifier(table));
+                if self.prepare(probe.as_slice()).is_ok() {
+                    return Ok(RowIdentity::RowId(alias.to_string()));
+                }
+            },
+            None => { }
+        }
+        // Without a primary key either, rows can't be told apart
+        let mut keys : Vec<&ColumnInfo> = info.iter().filter(|column| column.pk > 0).collect();
+        keys.sort_by(|a, b| a.pk.cmp(&b.pk));
+        if keys.is_empty() {
+            return Ok(RowIdentity::Nothing);
+        }
+        Ok(RowIdentity::PrimaryKey(keys.iter().map(|column| column.name.clone()).collect()))
+    }
+
+    /// Describes the columns of `table`, in order
+    pub fn table_info(&self, table : &str) -> Result<Vec<ColumnInfo>, SqliteError> {
+        let info = match self.exec(format!("pragma table_info({});", quote_identifier(table)).as_slice()) {
+            Ok(info) => { info },
+            Err(err) => { return Err(err); }
+        };
+        // Columns are (cid, name, type, notnull, dflt_value, pk)
+        Ok(info.rows.iter().map(|row| {
+            ColumnInfo {
+                name : row[1].to_text(),
+                decl_type : match row[2] {
+                    Value::Text(ref t) => t.clone(),
+                    _ => "".to_string()
+                },
+                not_null : row[3] == Value::Integer(1),
+                default : match row[4] {
+                    Value::Null => None,
+                    ref value => Some(value.to_text())
+                },
+                pk : match row[5] {
+                    Value::Integer(pk) if pk > 0 => pk as usize,
+                    _ => 0
+                }
+            }
+        }).collect())
+    }
+
+    /// Indexes on `table`, in the order SQLite lists them
+    pub fn indexes(&self, table : &str) -> Result<Vec<IndexInfo>, SqliteError> {
+        let list = match self.exec(format!("pragma index_list({});", quote_identifier(table)).as_slice()) {
+            Ok(list) => { list },
+            Err(err) => { return Err(err); }
+        };
+        let mut indexes : Vec<IndexInfo> = Vec::new();
+        // Columns are (seq, name, unique, ...)
+        for row in list.rows.iter() {
+            let name = row[1].to_text();
+            let info = match self.exec(format!("pragma index_info({});", quote_identifier(name.as_slice())).as_slice()) {
+                Ok(info) => { info },
+                Err(err) => { return Err(err); }
+            };
+            // Columns are (seqno, cid, name)
+            indexes.push(IndexInfo {
+                name : name,
+                unique : row[2] == Value::Integer(1),
+                columns : info.rows.iter().map(|column| {
+                    match column[2] {
+                        Value::Null => "?".to_string(),
+                        ref name => name.to_text()
+                    }
+                }).collect()
+            });
+        }
+        Ok(indexes)
+    }
+
+    /// Foreign keys of `table`, with the columns of each one grouped together
+    pub fn foreign_keys(&self, table : &str) -> Result<Vec<ForeignKey>, SqliteError> {
+        let list = match self.exec(format!("pragma foreign_key_list({});", quote_identifier(table)).as_slice()) {
+            Ok(list) => { list },
+            Err(err) => { return Err(err); }
+        };
+        // Columns are (id, seq, table, from, to, on_update, on_delete, match),
+        // one row per column with the columns of a key next to each other
+        let mut keys : Vec<(Value, ForeignKey)> = Vec::new();
+        for row in list.rows.iter() {
+            let same_key = keys.last().map(|&(ref id, _)| *id == row[0]).unwrap_or(false);
+            if !same_key {
+                keys.push((row[0].clone(), ForeignKey {
+                    columns : Vec::new(),
+                    table : row[2].to_text(),
+                    to : Vec::new(),
+                    on_update : row[5].to_text(),
+                    on_delete : row[6].to_text()
+                }));
+            }
+            let key = &mut keys.last_mut().unwrap().1;
+            key.columns.push(row[3].to_text());
+            match row[4] {
+                Value::Null => { },
+                ref to => { key.to.push(to.to_text()); }
+            }
+        }
+        Ok(keys.into_iter().map(|(_, key)| key).collect())
+    }
+
+    /// Triggers on `table`, as (name, sql) pairs
+    pub fn triggers(&self, table : &str) -> Result<Vec<(String, String)>, SqliteError> {
+        let mut stmt = match self.prepare(TRIGGERS_QUERY) {
+            Ok(stmt) => { stmt },
+            Err(err) => { return Err(err); }
+        };
+        match stmt.bind_text(1, table) {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+        match stmt.query() {
+            Ok(results) => {
+                Ok(results.rows.iter().map(|row| (row[0].to_text(), row[1].to_text())).collect())
+            },
+            Err(err) => { Err(err) }
+        }
+    }
+
+    /// The CREATE statement an object was made with, None for objects
+    /// SQLite made itself
+    pub fn object_sql(&self, name : &str) -> Result<Option<String>, SqliteError> {
+        let mut stmt = match self.prepare(OBJECT_SQL_QUERY) {
+            Ok(stmt) => { stmt },
+            Err(err) => { return Err(err); }
+        };
+        match stmt.bind_text(1, name) {
+            Ok(_) => { },
+            Err(err) => { return Err(err); }
+        };
+        match stmt.query() {
+            Ok(results) => {
+                Ok(match results.rows.get(0).map(|row| &row[0]) {
+                    Some(&Value::Text(ref sql)) => Some(sql.clone()),
+                    _ => None
+                })
+            },
+            Err(err) => { Err(err) }
         }
-        Ok(())
     }
 
-    /// Calls SQLite to list all tables
-    pub fn list_tables(&self) -> Result<(), String> {
-        self.exec(LIST_TABLES_QUERY, exec_cb)
+    /// Works out the value a column default expression gives, expressions
+    /// such as CURRENT_TIMESTAMP are evaluated now
+    pub fn eval_default(&self, expr : &str) -> Result<Value, SqliteError> {
+        match self.exec(format!("select {};", expr).as_slice()) {
+            Ok(mut results) => {
+                Ok(results.rows.pop().and_then(|mut row| row.pop()).unwrap_or(Value::Null))
+            },
+            Err(err) => { Err(err) }
+        }
     }
 
-    /// Dumps all table entries
-    pub fn dump_table(&self, table : String) -> Result<(), String> {
-        self.exec(format!("select rowid,* from {};", table).as_slice(), exec_cb)
+    /// Dumps all table entries, led by the rowid when the table has one
+    pub fn dump_table(&self, table : String) -> Result<ExecResult, SqliteError> {
+        let query = match self.row_identity(table.as_slice()) {
+            Ok(RowIdentity::RowId(alias)) => { format!("select {},* from", alias) },
+            Ok(_) => { "select * from".to_string() },
+            Err(err) => { return Err(err); }
+        };
+        self.exec(format!("{} {};", query, quote_identifier(table.as_slice())).as_slice())
+    }
+}
+
+/// The statement `sql` starts with, given SQLite read `read` bytes of it:
+/// up to the first `;` from there that completes a statement, or all of it
+fn statement_at(sql : String, read : usize) -> String {
+    for (i, c) in sql.char_indices() {
+        if c != ';' || i + 1 < read {
+            continue;
+        }
+        let statement = sql.slice_to(i + 1);
+        let cstatement = str_to_cstr(statement);
+        if unsafe { sqlite3_complete(cstatement.as_ptr()) } != 0 {
+            return statement.trim().to_string();
+        }
+    }
+    sql.trim().to_string()
+}
+
+/// A compiled SQL statement that can be bound, run and reused
+pub struct Statement {
+    /// Connection the statement was prepared on
+    sqlite : Sqlite,
+    /// A pointer to the SQLite statement
+    stmt : *const c_sqlite3_stmt,
+    /// Text of the statement, for error reporting
+    sql : String
+}
+
+impl Statement {
+    /// Most recent error, attributed to this statement
+    fn error(&self) -> SqliteError {
+        self.sqlite.error(Some(self.sql.clone()))
+    }
+
+    /// Turns a SQLite return code into a result
+    fn check(&self, ret : c_int) -> Result<(), SqliteError> {
+        match ret {
+            SQLITE_OK => { Ok(()) },
+            _ => { Err(self.error()) }
+        }
+    }
+
+    /// Looks up the position of a named parameter such as `:name`
+    pub fn parameter_index(&self, name : &str) -> Result<usize, SqliteError> {
+        let cname = str_to_cstr(name);
+        let idx = unsafe { sqlite3_bind_parameter_index(self.stmt, cname.as_ptr()) };
+        match idx {
+            0 => {
+                Err(SqliteError::new(SQLITE_RANGE,
+                                     format!("no such parameter: {}", name),
+                                     Some(self.sql.clone())))
+            },
+            _ => { Ok(idx as usize) }
+        }
+    }
+
+    /// Binds an integer to the parameter at `idx` (1-based)
+    pub fn bind_int(&mut self, idx : usize, val : i64) -> Result<(), SqliteError> {
+        let ret = unsafe { sqlite3_bind_int64(self.stmt, idx as c_int, val) };
+        self.check(ret)
+    }
+
+    /// Binds a float to the parameter at `idx` (1-based)
+    pub fn bind_float(&mut self, idx : usize, val : f64) -> Result<(), SqliteError> {
+        let ret = unsafe { sqlite3_bind_double(self.stmt, idx as c_int, val as c_double) };
+        self.check(ret)
+    }
+
+    /// Binds text to the parameter at `idx` (1-based)
+    pub fn bind_text(&mut self, idx : usize, val : &str) -> Result<(), SqliteError> {
+        let ret = unsafe {
+            sqlite3_bind_text(self.stmt,
+                              idx as c_int,
+                              val.as_ptr() as *const c_char,
+                              val.len() as c_int,
+                              SQLITE_TRANSIENT)
+        };
+        self.check(ret)
+    }
+
+    /// Binds a blob to the parameter at `idx` (1-based)
+    pub fn bind_blob(&mut self, idx : usize, val : &[u8]) -> Result<(), SqliteError> {
+        let ret = unsafe {
+            sqlite3_bind_blob(self.stmt,
+                              idx as c_int,
+                              val.as_ptr() as *const c_void,
+                              val.len() as c_int,
+                              SQLITE_TRANSIENT)
+        };
+        self.check(ret)
+    }
+
+    /// Binds a value of any storage class to the parameter at `idx` (1-based)
+    pub fn bind(&mut self, idx : usize, val : &Value) -> Result<(), SqliteError> {
+        match *val {
+            Value::Null => self.bind_null(idx),
+            Value::Integer(n) => self.bind_int(idx, n),
+            Value::Real(f) => self.bind_float(idx, f),
+            Value::Text(ref s) => self.bind_text(idx, s.as_slice()),
+            Value::Blob(ref b) => self.bind_blob(idx, b.as_slice())
+        }
+    }
+
+    /// Binds NULL to the parameter at `idx` (1-based)
+    pub fn bind_null(&mut self, idx : usize) -> Result<(), SqliteError> {
+        let ret = unsafe { sqlite3_bind_null(self.stmt, idx as c_int) };
+        self.check(ret)
+    }
+
+    /// Sets every parameter back to NULL
+    pub fn clear_bindings(&mut self) {
+        unsafe { sqlite3_clear_bindings(self.stmt); }
+    }
+
+    /// Rewinds the statement so it can be run again, bindings are kept
+    pub fn reset(&mut self) {
+        unsafe { sqlite3_reset(self.stmt); }
+    }
+
+    /// Advances to the next row, returns false once the statement is done
+    pub fn step(&mut self) -> Result<bool, SqliteError> {
+        let ret = unsafe { sqlite3_step(self.stmt) };
+        match ret {
+            SQLITE_ROW => { Ok(true) },
+            SQLITE_DONE => { Ok(false) },
+            _ => { Err(self.error()) }
+        }
+    }
+
+    /// Number of columns in each row
+    pub fn num_cols(&self) -> usize {
+        unsafe { sqlite3_column_count(self.stmt) as usize }
+    }
+
+    /// Names of the result columns
+    pub fn col_names(&self) -> Vec<String> {
+        (0..self.num_cols()).map(|i| {
+            cstr_to_str(unsafe { sqlite3_column_name(self.stmt, i as c_int) })
+        }).collect()
+    }
+
+    /// Value of column `col` in the current row
+    pub fn column_value(&self, col : usize) -> Value {
+        let col = col as c_int;
+        match unsafe { sqlite3_column_type(self.stmt, col) } {
+            SQLITE_INTEGER => {
+                Value::Integer(unsafe { sqlite3_column_int64(self.stmt, col) })
+            },
+            SQLITE_FLOAT => {
+                Value::Real(unsafe { sqlite3_column_double(self.stmt, col) } as f64)
+            },
+            SQLITE_TEXT => {
+                // Fetch the pointer before the length, as the docs ask
+                let text = unsafe { sqlite3_column_text(self.stmt, col) };
+                let len = unsafe { sqlite3_column_bytes(self.stmt, col) } as usize;
+                let bytes = bytes_to_vec(text, len);
+                Value::Text(String::from_utf8_lossy(bytes.as_slice()).into_owned())
+            },
+            SQLITE_BLOB => {
+                let blob = unsafe { sqlite3_column_blob(self.stmt, col) };
+                let len = unsafe { sqlite3_column_bytes(self.stmt, col) } as usize;
+                Value::Blob(bytes_to_vec(blob, len))
+            },
+            // SQLITE_NULL
+            _ => { Value::Null }
+        }
+    }
+
+    /// Runs the statement to completion, returns the number of rows changed.
+    /// Statements that change nothing, such as queries and DDL, return 0
+    /// rather than the count left over from the last INSERT, UPDATE or DELETE.
+    pub fn execute(&mut self) -> Result<usize, SqliteError> {
+        self.reset();
+        let before = self.sqlite.total_changes();
+        loop {
+            match self.step() {
+                Ok(true) => { continue; },
+                Ok(false) => { break; },
+                Err(err) => { return Err(err); }
+            }
+        }
+        match self.sqlite.total_changes() == before {
+            true => Ok(0),
+            false => Ok(self.sqlite.changes())
+        }
+    }
+
+    /// Runs the statement and collects every row
+    pub fn query(&mut self) -> Result<ExecResult, SqliteError> {
+        self.reset();
+        let num_cols = self.num_cols();
+        let mut results = ExecResult::new();
+        results.num_cols = Some(num_cols);
+        results.col_names = Some(self.col_names());
+        loop {
+            match self.step() {
+                Ok(true) => {
+                    let row = (0..num_cols).map(|i| self.column_value(i)).collect();
+                    results.rows.push(row);
+                },
+                Ok(false) => { break; },
+                Err(err) => { return Err(err); }
+            }
+        }
+        Ok(results)
+    }
+}
+
+impl Drop for Statement {
+    /// Destroys the prepared statement
+    fn drop(&mut self) {
+        unsafe { sqlite3_finalize(self.stmt); };
     }
 }
\ No newline at end of file
@@ -1,16 +1,26 @@
-use csgui::{CSG};
-use csgui::{KEY_h, KEY_j, KEY_k, KEY_l, KEY_e, KEY_q};
+use csgui::{CSG, Theme};
+use curses::{Key, A_NORMAL, A_DIM, A_REVERSE};
+use pager::{TablePager};
+use keymap::{Keymap, Action};
+use config::{Config};
+use json::{pretty};
+use csv::{CsvWriter};
+use std::io::{File};
+use std::os;
+use curses::{COLOR_RED};
+use sqlite::{Sqlite, Value, OpenMode, ObjectKind, RowIdentity, ErrorCode, quote_identifier};
+use csgui::{KEY_h, KEY_j, KEY_k, KEY_l, KEY_e, KEY_q, KEY_i, KEY_d, KEY_w, KEY_colon, KEY_s, KEY_dot};
 
 #[test]
 fn gui_setup() {
-	let my_csgui = CSG::new("test.db").unwrap();
+	let my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
 	assert_eq!(my_csgui.active_screen, 0);
 	assert_eq!(my_csgui.screens.len(), 1);
 }
 
 #[test]
 fn main_screen_invalid_movement() {
-	let mut my_csgui = CSG::new("test.db").unwrap();
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
 	// Coords should not change when moving outside boundary
 	let coords = my_csgui.get_active_window_coords();
 	let result = my_csgui.dispatch_key(KEY_h);
@@ -21,7 +31,7 @@ fn main_screen_invalid_movement() {
 
 #[test]
 fn main_screen_valid_movement() {
-	let mut my_csgui = CSG::new("test.db").unwrap();
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
 	// Coords should change when moving inside boundary
 	let coords = my_csgui.get_active_window_coords();
 	let result = my_csgui.dispatch_key(KEY_j);
@@ -33,7 +43,7 @@ fn main_screen_valid_movement() {
 #[test]
 fn main_screen_edit() {
 	// Edit should activate table dump screen
-	let mut my_csgui = CSG::new("test.db").unwrap();
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
 	assert_eq!(my_csgui.active_screen, 0);
 	let result = my_csgui.dispatch_key(KEY_e);
 	assert!(result.is_some());
@@ -43,14 +53,55 @@ fn main_screen_edit() {
 #[test]
 fn main_screen_quit() {
 	// Quit should cease mainloop execution by returning None
-	let mut my_csgui = CSG::new("test.db").unwrap();
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
 	let result = my_csgui.dispatch_key(KEY_q);
 	assert!(result.is_none());
 }
 
-// #[test]
+#[test]
+fn main_screen_lists_schema() {
+	let mut my_csgui = CSG::new(":memory:", OpenMode::ReadWrite).unwrap();
+	my_csgui.run_query("create table a (x integer primary key autoincrement); \
+	                    create index a_x on a (x); insert into a values (null);").unwrap();
+	my_csgui.dispatch_key(KEY_q);
+
+	// Reading the list again shows the new objects, sqlite_sequence too
+	assert!(my_csgui.dispatch_key(KEY_dot).unwrap().is_ok());
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "a");
+	my_csgui.dispatch_key(KEY_l);
+	my_csgui.dispatch_key(KEY_l);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "1");
+	my_csgui.dispatch_key(KEY_j);
+	my_csgui.dispatch_key(KEY_h);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "table");
+	my_csgui.dispatch_key(KEY_h);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "sqlite_sequence");
+
+	// Hidden again, the selection goes back to the top
+	assert!(my_csgui.dispatch_key(KEY_dot).unwrap().is_ok());
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "a");
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "a_x");
+	// Indexes open the schema of their table
+	assert!(my_csgui.dispatch_key(KEY_e).unwrap().is_ok());
+	assert_eq!(my_csgui.active_screen, 1);
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "x");
+}
+
+#[test]
+fn theme_styles_values() {
+	let theme = Theme::monochrome(&Config::new());
+	assert_eq!(theme.selected, A_REVERSE);
+	assert_eq!(theme.value(&Value::Null), A_DIM);
+	assert_eq!(theme.value(&Value::Text("".to_string())), A_NORMAL);
+	// Without colors, NULL still looks different from the empty string
+	assert!(theme.value(&Value::Null) != theme.value(&Value::Text("".to_string())));
+}
+
+#[test]
 fn table_dump_screen_invalid_movement() {
-	let mut my_csgui = CSG::new("test.db").unwrap();
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
 	// Coords should not change, column titles are not selectable
 	let mut result = my_csgui.dispatch_key(KEY_e);
 	assert!(result.is_some());
@@ -61,9 +112,9 @@ fn table_dump_screen_invalid_movement() {
 	assert_eq!(coords, new_coords);
 }
 
-// #[test]
+#[test]
 fn table_dump_screen_valid_movement() {
-	let mut my_csgui = CSG::new("test.db").unwrap();
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
 	// Movement to a new column is allowed
 	let mut result = my_csgui.dispatch_key(KEY_e);
 	assert!(result.is_some());
@@ -76,8 +127,8 @@ fn table_dump_screen_valid_movement() {
 
 #[test]
 fn table_dump_screen_edit() {
-	// XXX: Edit unsupported
-	let mut my_csgui = CSG::new("test.db").unwrap();
+	// Escape leaves the editor without touching the table
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
 	assert_eq!(my_csgui.active_screen, 0);
 	let mut result = my_csgui.dispatch_key(KEY_e);
 	assert!(result.is_some());
@@ -85,12 +136,120 @@ fn table_dump_screen_edit() {
 	result = my_csgui.dispatch_key(KEY_e);
 	assert!(result.is_some());
 	assert_eq!(my_csgui.active_screen, 1);
+	// q is typed into the editor rather than leaving the screen
+	result = my_csgui.dispatch_key(KEY_q);
+	assert!(result.is_some());
+	assert_eq!(my_csgui.active_screen, 1);
+	result = my_csgui.dispatch_key(27);
+	assert!(result.is_some());
+	result = my_csgui.dispatch_key(KEY_q);
+	assert!(result.is_some());
+	assert_eq!(my_csgui.active_screen, 0);
+}
+
+#[test]
+fn table_dump_screen_edit_writes_back() {
+	let mut my_csgui = CSG::new(":memory:", OpenMode::ReadWrite).unwrap();
+	my_csgui.run_query("create table t (a integer primary key, b text check (length(b) < 3)); \
+	                    insert into t values (1, 'x');").unwrap();
+	my_csgui.dispatch_key(KEY_q);
+	my_csgui.open_table("t").unwrap();
+	// Past the rowid and a, onto b
+	my_csgui.dispatch_key(KEY_l);
+	my_csgui.dispatch_key(KEY_l);
+	for &key in [KEY_e, 127, 'y' as usize, 13].iter() {
+		assert!(my_csgui.dispatch_key(key).unwrap().is_ok());
+	}
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "y");
+
+	// A failed constraint is reported without leaving the screen
+	for &key in [KEY_e, 'y' as usize, 'y' as usize, 13].iter() {
+		assert!(my_csgui.dispatch_key(key).unwrap().is_ok());
+	}
+	assert_eq!(my_csgui.active_screen, 1);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "y");
+}
+
+#[test]
+fn table_dump_screen_insert_and_delete() {
+	let mut my_csgui = CSG::new(":memory:", OpenMode::ReadWrite).unwrap();
+	my_csgui.run_query("create table t (a integer primary key, b text not null default 'new');").unwrap();
+	my_csgui.dispatch_key(KEY_q);
+	my_csgui.open_table("t").unwrap();
+
+	// The form starts on the value of a, b holds its default
+	assert!(my_csgui.dispatch_key(KEY_i).unwrap().is_ok());
+	assert_eq!(my_csgui.active_screen, 2);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "<NULL>");
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "new");
+	assert!(my_csgui.dispatch_key(KEY_w).unwrap().is_ok());
+	assert_eq!(my_csgui.active_screen, 1);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "1");
+
+	// Anything but yes keeps the row
+	for &key in [KEY_d, 'n' as usize, 13, KEY_d, 'y' as usize, 13].iter() {
+		assert!(my_csgui.dispatch_key(key).unwrap().is_ok());
+		assert_eq!(my_csgui.active_screen, 1);
+	}
+	assert!(my_csgui.get_active_window().is_none());
+}
+
+// Types a line into the open prompt and enters it
+fn type_line(csgui : &mut CSG, line : &str) {
+	for c in line.chars() {
+		assert!(csgui.dispatch_key(c as usize).unwrap().is_ok());
+	}
+	assert!(csgui.dispatch_key(13).unwrap().is_ok());
+}
+
+#[test]
+fn console_screen() {
+	let mut my_csgui = CSG::new(":memory:", OpenMode::ReadWrite).unwrap();
+	assert!(my_csgui.dispatch_key(KEY_colon).unwrap().is_ok());
+	assert_eq!(my_csgui.active_screen, 1);
+	type_line(&mut my_csgui, "create table t (a); insert into t values (1), (2);");
+	assert!(my_csgui.get_active_window().is_none());
+
+	// Errors are shown without leaving the console
+	my_csgui.dispatch_key(KEY_e);
+	type_line(&mut my_csgui, "select * from nope");
+	assert_eq!(my_csgui.active_screen, 1);
+
+	my_csgui.dispatch_key(KEY_e);
+	type_line(&mut my_csgui, "select a * 10 from t");
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "10");
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "20");
+
+	// Up recalls the last statement
+	my_csgui.dispatch_key(KEY_e);
+	my_csgui.dispatch_key(259);
+	my_csgui.dispatch_key(13);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "10");
+	my_csgui.dispatch_key(KEY_q);
+	assert_eq!(my_csgui.active_screen, 0);
+}
+
+#[test]
+fn schema_screen() {
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadOnly).unwrap();
+	assert!(my_csgui.dispatch_key(KEY_s).unwrap().is_ok());
+	assert_eq!(my_csgui.active_screen, 1);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "column");
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "apples");
+	// Nothing to edit here
+	my_csgui.dispatch_key(KEY_e);
+	assert_eq!(my_csgui.active_screen, 1);
+	my_csgui.dispatch_key(KEY_q);
+	assert_eq!(my_csgui.active_screen, 0);
 }
 
 #[test]
 fn table_dump_screen_quit() {
 	// Quit should return to main screen and not exit mainloop
-	let mut my_csgui = CSG::new("test.db").unwrap();
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
 	assert_eq!(my_csgui.active_screen, 0);
 	let mut result = my_csgui.dispatch_key(KEY_e);
 	assert!(result.is_some());
@@ -98,4 +257,576 @@ fn table_dump_screen_quit() {
 	result = my_csgui.dispatch_key(KEY_q);
 	assert!(result.is_some());
 	assert_eq!(my_csgui.active_screen, 0);
-}
\ No newline at end of file
+}
+
+#[test]
+fn table_dump_screen_scrolls() {
+	// Moving past the last visible row keeps the selection on screen
+	let mut my_csgui = CSG::new(":memory:", OpenMode::ReadWrite).unwrap();
+	my_csgui.run_query("with recursive n(i) as (select 1 union all select i + 1 from n limit 500)
+	                    select i, i * 2 from n").unwrap();
+	for _ in (0..499) {
+		assert!(my_csgui.dispatch_key(KEY_j).is_some());
+	}
+	assert_eq!(my_csgui.get_active_window_coords(), (0, 499));
+	assert!(my_csgui.get_active_window().is_some());
+	// Moving past the end of the data does nothing
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window_coords(), (0, 499));
+	my_csgui.dispatch_key(KEY_l);
+	assert_eq!(my_csgui.get_active_window_coords(), (1, 499));
+	assert!(my_csgui.get_active_window().is_some());
+}
+
+#[test]
+fn key_codes() {
+	assert_eq!(Key::from_code(13), Key::Enter);
+	assert_eq!(Key::from_code(127), Key::Backspace);
+	assert_eq!(Key::from_code(259), Key::Up);
+	assert_eq!(Key::from_code(338), Key::PageDown);
+	assert_eq!(Key::from_code(21), Key::Ctrl('u'));
+	assert_eq!(Key::from_code(36), Key::Char('$'));
+	assert_eq!(Key::from_code(0xc3), Key::Byte(0xc3));
+}
+
+#[test]
+fn keymap_sequences() {
+	let mut keymap = Keymap::load("# comment\n\
+	                               first-row = \"gg\"\n\
+	                               back = [\"q\", \"C-c\"]").unwrap();
+	assert_eq!(keymap.feed(Key::Char('g')), None);
+	assert_eq!(keymap.feed(Key::Char('g')), Some(Action::FirstRow));
+	assert_eq!(keymap.feed(Key::Ctrl('c')), Some(Action::Back));
+	assert_eq!(keymap.feed(Key::Char('x')), None);
+	// A key that breaks a sequence starts a new one
+	assert_eq!(keymap.feed(Key::Char('g')), None);
+	assert_eq!(keymap.feed(Key::Char('j')), Some(Action::MoveDown));
+	// Defaults of rebound actions are gone, the rest stay
+	assert_eq!(keymap.lookup(&[Key::Home]), None);
+	assert_eq!(keymap.lookup(&[Key::Left]), Some(Action::MoveLeft));
+
+	assert!(Keymap::load("back = \"q\"\nnope = \"x\"").err().unwrap().starts_with("line 2"));
+	assert!(Keymap::load("back = q").is_err());
+	assert!(Keymap::load("back = [\"q\"").is_err());
+	// g alone would hide gG
+	assert_eq!(Keymap::load("last-row = \"gG\"").err().unwrap(),
+	           "line 1: `gG` starts with keys bound to first-row");
+	// Whichever order they come in, the later line is the one at fault
+	assert_eq!(Keymap::load("first-row = \"gg\"\nlast-row = \"g\"").err().unwrap(),
+	           "line 2: `g` is the start of keys bound to first-row");
+	assert_eq!(Keymap::load("last-row = \"g\"\nfirst-row = \"gg\"").err().unwrap(),
+	           "line 2: `gg` starts with keys bound to last-row");
+	// Rebinding g on a later line makes way for gG
+	assert!(Keymap::load("last-row = \"gG\"\nfirst-row = \"gg\"").is_ok());
+	assert!(Keymap::load("first-row = \"gg\"\nlast-row = \"gG\"").is_ok());
+
+	// A keymap file goes over bindings already made
+	let mut keymap = Keymap::load("move-down = \"C-n\"").unwrap();
+	keymap.load_into("move-up = \"C-p\" # emacs").unwrap();
+	assert_eq!(keymap.lookup(&[Key::Ctrl('n')]), Some(Action::MoveDown));
+	assert_eq!(keymap.lookup(&[Key::Ctrl('p')]), Some(Action::MoveUp));
+}
+
+#[test]
+fn config_settings() {
+	let config = Config::parse("# comment\n\
+	                            [display]\n\
+	                            cell-width = 12\n\
+	                            null = \"-\"\n\
+	                            [colors]\n\
+	                            null = \"red\"\n\
+	                            [database]\n\
+	                            mode = \"read-only\"\n\
+	                            [keys]\n\
+	                            move-down = [\"C-n\", \"Down\"]", "config.toml").unwrap();
+	assert_eq!(config.cell_width, 12);
+	assert_eq!(config.null_text, "-");
+	assert_eq!(config.null_color, COLOR_RED);
+	assert_eq!(config.mode, OpenMode::ReadOnly);
+	assert_eq!(config.history_size, Config::new().history_size);
+	assert_eq!(config.keymap.lookup(&[Key::Ctrl('n')]), Some(Action::MoveDown));
+	assert_eq!(config.keymap.lookup(&[Key::Char('j')]), None);
+
+	// Comments may follow a value, a # inside a string isn't one
+	let config = Config::parse("[database]   # how files open\n\
+	                            mode = \"read-only\"     # or \"read-write\", \"create\"\n\
+	                            [display]\n\
+	                            null = \"\\u2205 \\\"#\\\"\\t\"", "config.toml").unwrap();
+	assert_eq!(config.mode, OpenMode::ReadOnly);
+	assert_eq!(config.null_text, "\u{2205} \"#\"\t");
+	assert!(Config::parse("[display]\nnull = \"\\q\"", "c").is_err());
+
+	let err = Config::parse("[display]\nwidth = 3", "config.toml").err().unwrap();
+	assert_eq!(format!("{}", err), "config.toml:2: unknown setting `width` in [display]");
+	assert_eq!(Config::parse("[colors]\nblob = \"pink\"", "c").err().unwrap().line, 2);
+	assert!(Config::parse("[keys]\nnope = \"x\"", "c").is_err());
+	// Overlapping keys are found once all of them are read
+	assert_eq!(Config::parse("[keys]\nlast-row = \"g\"\n[display]\ncell-width = 12\n\
+	                          [keys]\nfirst-row = \"gg\"", "c").err().unwrap().line, 6);
+	assert_eq!(Config::parse("[export]\ndelimiter = \"tab\"", "c").unwrap().csv_delimiter, '\t');
+	assert!(Config::parse("[export]\ndelimiter = \";;\"", "c").is_err());
+	// A missing file is only an error when it was asked for
+	assert!(Config::load(Some("/nonexistent/csg.toml")).is_err());
+}
+
+#[test]
+fn main_screen_rebound_keys() {
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
+	my_csgui.set_keymap(Keymap::load("move-down = \"C-n\"").unwrap());
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window_coords(), (0, 0));
+	my_csgui.dispatch_key(14);
+	assert_eq!(my_csgui.get_active_window_coords(), (0, 1));
+}
+
+#[test]
+fn table_dump_screen_jumps() {
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
+	// Enter drills in like e
+	my_csgui.dispatch(Key::Enter);
+	assert_eq!(my_csgui.active_screen, 1);
+	let steps = [(Key::Char('G'), (0, 4)), (Key::Char('g'), (0, 0)), (Key::Char('$'), (3, 0)),
+	             (Key::Down, (3, 1)), (Key::Char('0'), (0, 1)), (Key::Right, (1, 1)),
+	             (Key::PageDown, (1, 4)), (Key::PageUp, (1, 0)), (Key::End, (1, 4)), (Key::Home, (1, 0))];
+	for &(key, coords) in steps.iter() {
+		assert!(my_csgui.dispatch(key).unwrap().is_ok());
+		assert_eq!(my_csgui.get_active_window_coords(), coords);
+	}
+}
+
+#[test]
+fn table_dump_screen_resize() {
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
+	my_csgui.dispatch_key(KEY_e);
+	my_csgui.dispatch_key(KEY_j);
+	my_csgui.dispatch_key(KEY_j);
+	my_csgui.dispatch_key(KEY_l);
+	let coords = my_csgui.get_active_window_coords();
+	let text = my_csgui.get_active_window().unwrap().get_text().to_string();
+
+	// Down to a single row, the selection stays put and in view
+	for &(width, height) in [(40, 3), (200, 60), (1, 0)].iter() {
+		assert!(my_csgui.resize(width, height).is_ok());
+		assert_eq!(my_csgui.get_active_window_coords(), coords);
+		assert_eq!(my_csgui.get_active_window().unwrap().get_text(), text.as_slice());
+	}
+}
+
+#[test]
+fn query_result_column_widths() {
+	let mut my_csgui = CSG::new(":memory:", OpenMode::ReadWrite).unwrap();
+	my_csgui.run_query("select 1 as a, 'abcdefghijklmnopqrstuvwxyz0123456789' as b, \
+	                    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20").unwrap();
+	my_csgui.resize(80, 10).unwrap();
+	// Long values are cut to the widest a column gets
+	my_csgui.dispatch_key(KEY_l);
+	let text = my_csgui.get_active_window().unwrap().get_text().to_string();
+	assert_eq!(text.chars().count(), 32);
+	assert!(text.ends_with("…"));
+	// Widened, more of the value shows
+	my_csgui.dispatch_key('>' as usize);
+	let text = my_csgui.get_active_window().unwrap().get_text().to_string();
+	assert_eq!(text.chars().count(), 34);
+	// Narrow columns scroll into view
+	for _ in (0..19) {
+		my_csgui.dispatch_key(KEY_l);
+	}
+	assert_eq!(my_csgui.get_active_window_coords(), (19, 0));
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "20");
+
+	// Wide characters take two columns each
+	my_csgui.run_query("select '漢字漢字漢字漢字漢字漢字漢字漢字漢字漢字' as w").unwrap();
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "漢字漢字漢字漢字漢字漢字漢字漢…");
+}
+
+#[test]
+fn json_pretty() {
+	assert_eq!(pretty("{\"a\": [1, 2.5e-3, {}], \"b\" :\"x, \\\"y\\\"\"}").unwrap(),
+	           "{\n  \"a\": [\n    1,\n    2.5e-3,\n    {}\n  ],\n  \"b\": \"x, \\\"y\\\"\"\n}");
+	assert_eq!(pretty(" [] ").unwrap(), "[]");
+	// Scalars are left alone, broken JSON isn't JSON
+	assert_eq!(pretty("12"), None);
+	assert_eq!(pretty("[1, 2"), None);
+	assert_eq!(pretty("[01]"), None);
+	assert_eq!(pretty("{\"a\" 1}"), None);
+	assert_eq!(pretty("[true] x"), None);
+	// Too deep to lay out
+	let deep : String = (0..100000).map(|_| '[').collect();
+	assert_eq!(pretty(deep.as_slice()), None);
+	let nested = format!("{}{}", (0..512).map(|_| "[").collect::<String>(), (0..512).map(|_| "]").collect::<String>());
+	assert!(pretty(nested.as_slice()).is_some());
+}
+
+#[test]
+fn table_dump_screen_cell_view() {
+	let mut my_csgui = CSG::new(":memory:", OpenMode::ReadWrite).unwrap();
+	my_csgui.run_query("create table t (a integer primary key, b text); \
+	                    insert into t values (1, '{\"k\": [1, 2]}');").unwrap();
+	my_csgui.dispatch_key(KEY_q);
+	my_csgui.open_table("t").unwrap();
+	my_csgui.resize(80, 10).unwrap();
+	my_csgui.dispatch_key(KEY_l);
+	my_csgui.dispatch_key(KEY_l);
+	assert!(my_csgui.dispatch_key('v' as usize).unwrap().is_ok());
+	assert_eq!(my_csgui.active_screen, 2);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "{");
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "  \"k\": [");
+
+	// Long lines wrap to the width of the screen
+	my_csgui.dispatch_key(KEY_q);
+	my_csgui.run_query("select 'abcdefghij' as x").unwrap();
+	my_csgui.resize(4, 10).unwrap();
+	my_csgui.dispatch_key('v' as usize);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "abcd");
+	my_csgui.dispatch_key(KEY_j);
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "ij");
+	// and again when it changes size
+	my_csgui.resize(5, 10).unwrap();
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "fghij");
+
+	// Wide characters take two columns and aren't split across rows
+	my_csgui.dispatch_key(KEY_q);
+	my_csgui.run_query("select '漢字漢字漢' as x").unwrap();
+	my_csgui.resize(5, 10).unwrap();
+	my_csgui.dispatch_key('v' as usize);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "漢字");
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "漢字");
+	my_csgui.dispatch_key(KEY_j);
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "漢");
+}
+
+#[test]
+fn query_result_hex_view() {
+	let mut my_csgui = CSG::new(":memory:", OpenMode::ReadWrite).unwrap();
+	my_csgui.run_query("select cast('ABCDEFGHIJKLMNOPQRSTUVWXYZ' as blob) as b").unwrap();
+	my_csgui.resize(80, 10).unwrap();
+	assert!(my_csgui.dispatch_key('v' as usize).unwrap().is_ok());
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(),
+	           "00000000  41 42 43 44 45 46 47 48  49 4a 4b 4c 4d 4e 4f 50 |ABCDEFGHIJKLMNOP|");
+
+	// Offsets are typed in hex
+	type_line(&mut my_csgui, "o19");
+	assert_eq!(my_csgui.get_active_window_coords(), (0, 1));
+	assert!(my_csgui.get_active_window().unwrap().get_text().starts_with("00000010  51 52"));
+	type_line(&mut my_csgui, "ozz");
+	assert_eq!(my_csgui.get_active_window_coords(), (0, 1));
+
+	// Narrower, fewer bytes fit on a line but the same ones stay selected
+	my_csgui.resize(40, 10).unwrap();
+	assert_eq!(my_csgui.get_active_window().unwrap().get_text(), "00000010  51 52 53 54 |QRST|");
+}
+
+#[test]
+fn csv_quoting() {
+	let mut csv = CsvWriter::new(Vec::new(), ',');
+	csv.write_header(&["a".to_string(), "b,c".to_string()]).unwrap();
+	csv.write_row(&[Value::Null, Value::Text("".to_string())]).unwrap();
+	csv.write_row(&[Value::Text("say \"hi\"".to_string()), Value::Text("two\nlines".to_string())]).unwrap();
+	csv.write_row(&[Value::Real(1.5), Value::Blob(vec![0u8, 255u8])]).unwrap();
+	let written = String::from_utf8(csv.finish().unwrap()).unwrap();
+	assert_eq!(written, "a,\"b,c\"\r\n\
+	                     ,\"\"\r\n\
+	                     \"say \"\"hi\"\"\",\"two\nlines\"\r\n\
+	                     1.5,00ff\r\n");
+
+	// Only the delimiter in use needs quoting
+	let mut csv = CsvWriter::new(Vec::new(), ';');
+	csv.write_row(&[Value::Text("a,b".to_string()), Value::Text("c;d".to_string())]).unwrap();
+	assert_eq!(String::from_utf8(csv.finish().unwrap()).unwrap(), "a,b;\"c;d\"\r\n");
+
+	// A lone NULL is an empty line, the empty string is still quoted
+	let mut csv = CsvWriter::new(Vec::new(), ',');
+	csv.write_row(&[Value::Null]).unwrap();
+	csv.write_row(&[Value::Text("".to_string())]).unwrap();
+	assert_eq!(String::from_utf8(csv.finish().unwrap()).unwrap(), "\r\n\"\"\r\n");
+}
+
+#[test]
+fn table_dump_screen_export() {
+	let mut my_csgui = CSG::new(":memory:", OpenMode::ReadWrite).unwrap();
+	my_csgui.run_query("create table t (a integer primary key, b text); \
+	                    insert into t values (2, ''); insert into t values (1, null);").unwrap();
+	my_csgui.dispatch_key(KEY_q);
+	my_csgui.open_table("t").unwrap();
+	let path = os::tmpdir().join("csg_table_dump_screen_export.csv");
+	File::create(&path).write_str("keep").unwrap();
+	let export = |csgui : &mut CSG, answer : &str| {
+		// Replace the suggested name
+		csgui.dispatch_key('x' as usize);
+		csgui.dispatch_key(21);
+		type_line(csgui, path.as_str().unwrap());
+		type_line(csgui, answer);
+	};
+
+	// The file exists, so it is only replaced when told to
+	export(&mut my_csgui, "n");
+	assert_eq!(File::open(&path).read_to_string().unwrap(), "keep");
+	export(&mut my_csgui, "y");
+	let written = File::open(&path).read_to_string().unwrap();
+	assert_eq!(written, "a,b\r\n1,\r\n2,\"\"\r\n");
+	assert_eq!(my_csgui.active_screen, 1);
+}
+
+#[test]
+fn open_table_directly() {
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
+	assert!(my_csgui.open_table("people").is_ok());
+	assert_eq!(my_csgui.active_screen, 1);
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadWrite).unwrap();
+	assert!(my_csgui.open_table("no such table").is_err());
+	assert_eq!(my_csgui.active_screen, 0);
+}
+
+#[test]
+fn run_query_directly() {
+	let mut my_csgui = CSG::new("test.db", OpenMode::ReadOnly).unwrap();
+	assert!(my_csgui.run_query("select count(*) from fruits").is_ok());
+	assert_eq!(my_csgui.active_screen, 1);
+	assert!(my_csgui.run_query("select * from").is_err());
+}
+
+#[test]
+fn sqlite_results_are_independent() {
+	// Each query owns its results, so two can be held at once
+	let sqlite = Sqlite::new("test.db").unwrap();
+	let tables = sqlite.list_tables().unwrap();
+	let fruits = sqlite.dump_table("fruits".to_string()).unwrap();
+	assert_eq!(tables.rows.len(), 2);
+	assert_eq!(fruits.rows.len(), 5);
+	assert_eq!(fruits.num_cols, Some(4));
+}
+
+#[test]
+fn sqlite_prepared_statement_reuse() {
+	let sqlite = Sqlite::new(":memory:").unwrap();
+	sqlite.prepare("create table t (a, b)").unwrap().execute().unwrap();
+	let mut insert = sqlite.prepare("insert into t values (?, :b)").unwrap();
+	let b = insert.parameter_index(":b").unwrap();
+	assert_eq!(b, 2);
+	insert.bind_int(1, 1).unwrap();
+	insert.bind_text(b, "one").unwrap();
+	assert_eq!(insert.execute().unwrap(), 1);
+	insert.bind_float(1, 2.5).unwrap();
+	insert.bind_null(b).unwrap();
+	assert_eq!(insert.execute().unwrap(), 1);
+	// Nothing changed, whatever the last insert did
+	assert_eq!(sqlite.prepare("select a from t").unwrap().execute().unwrap(), 0);
+	assert_eq!(sqlite.prepare("create table u (c)").unwrap().execute().unwrap(), 0);
+	let rows = sqlite.prepare("select a, b from t order by a").unwrap().query().unwrap();
+	assert_eq!(rows.rows.len(), 2);
+	assert_eq!(rows.rows[0][1], Value::Text("one".to_string()));
+	assert_eq!(rows.rows[1][0], Value::Real(2.5));
+	assert_eq!(rows.rows[1][1], Value::Null);
+	assert!(insert.parameter_index(":missing").is_err());
+}
+
+#[test]
+fn sqlite_typed_values() {
+	let sqlite = Sqlite::new(":memory:").unwrap();
+	let results = sqlite.exec("select 1, 1.0, 'a', x'00ff', null, ''").unwrap();
+	let row = &results.rows[0];
+	assert_eq!(row[0], Value::Integer(1));
+	assert_eq!(row[1].to_text(), "1.0".to_string());
+	assert_eq!(row[2], Value::Text("a".to_string()));
+	assert_eq!(row[3], Value::Blob(vec![0u8, 255u8]));
+	assert_eq!(row[3].to_text(), "<BLOB 2 bytes> 00 ff");
+	assert_eq!(row[4], Value::Null);
+	// NULL and the empty string must not look alike
+	assert!(row[4].to_text() != row[5].to_text());
+}
+
+#[test]
+fn sqlite_quoted_identifiers() {
+	assert_eq!(quote_identifier("order"), "\"order\"".to_string());
+	assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"".to_string());
+
+	let sqlite = Sqlite::new(":memory:").unwrap();
+	sqlite.exec("create table \"order\" (a); create table \"Line Items\" (b);
+	             create table \"we\"\"ird\" (c); insert into \"Line Items\" values (1);").unwrap();
+	let tables = sqlite.list_tables().unwrap();
+	assert_eq!(tables.rows.len(), 3);
+	for row in tables.rows.iter() {
+		assert!(sqlite.dump_table(row[0].to_text()).is_ok());
+	}
+	assert_eq!(sqlite.dump_table("Line Items".to_string()).unwrap().rows.len(), 1);
+}
+
+#[test]
+fn sqlite_views_and_without_rowid() {
+	let sqlite = Sqlite::new(":memory:").unwrap();
+	sqlite.exec("create table kv (k, v, primary key (v, k)) without rowid;
+	             create view kv_view as select * from kv;
+	             insert into kv values ('a', 1);").unwrap();
+	let objects = sqlite.list_objects().unwrap();
+	assert_eq!(objects.len(), 2);
+	assert_eq!(objects[1].kind, ObjectKind::View);
+	assert!(objects[1].is_read_only());
+	assert_eq!(sqlite.row_identity("kv").unwrap(),
+	           RowIdentity::PrimaryKey(vec!["v".to_string(), "k".to_string()]));
+	assert_eq!(sqlite.row_identity("kv_view").unwrap(), RowIdentity::Nothing);
+	assert_eq!(sqlite.dump_table("kv".to_string()).unwrap().num_cols, Some(2));
+	assert_eq!(sqlite.dump_table("kv_view".to_string()).unwrap().rows.len(), 1);
+}
+
+#[test]
+fn sqlite_shadowed_rowid() {
+	let sqlite = Sqlite::new(":memory:").unwrap();
+	sqlite.exec("create table tags (rowid, tag);
+	             create table kv (rowid, v, primary key (rowid)) without rowid;
+	             create table all_taken (rowid, _rowid_, oid);
+	             insert into tags values (1, 'a'), (1, 'b');").unwrap();
+	// The column named rowid isn't unique, the real one is still there
+	assert_eq!(sqlite.row_identity("tags").unwrap(), RowIdentity::RowId("_rowid_".to_string()));
+	assert_eq!(sqlite.row_identity("kv").unwrap(), RowIdentity::PrimaryKey(vec!["rowid".to_string()]));
+	assert_eq!(sqlite.row_identity("all_taken").unwrap(), RowIdentity::Nothing);
+
+	let mut pager = TablePager::new(sqlite.clone(), "tags").unwrap();
+	pager.fetch(0, 2).unwrap();
+	pager.update(1, 2, &Value::Text("c".to_string())).unwrap();
+	assert_eq!(pager.get(0).unwrap()[2], Value::Text("a".to_string()));
+	assert_eq!(pager.get(1).unwrap()[2], Value::Text("c".to_string()));
+
+	let mut pager = TablePager::new(sqlite.clone(), "all_taken").unwrap();
+	pager.fetch(0, 1).unwrap();
+	assert!(pager.update(0, 0, &Value::Integer(1)).is_err());
+}
+
+#[test]
+fn sqlite_error_codes() {
+	// Opening a file that isn't a database fails up front
+	match Sqlite::new("src/lib.rs") {
+		Ok(_) => { panic!("opened a source file as a database"); },
+		Err(err) => { assert_eq!(err.code, ErrorCode::NotADb); }
+	}
+
+	let sqlite = Sqlite::new(":memory:").unwrap();
+	let err = sqlite.exec("select * from missing;").err().unwrap();
+	assert_eq!(err.code, ErrorCode::Error);
+	assert_eq!(err.sql, Some("select * from missing;".to_string()));
+	// Only the statement that failed, wherever SQLite stopped reading it
+	let err = sqlite.exec("select 1;\n select ';' from missing; select 2;").err().unwrap();
+	assert_eq!(err.sql, Some("select ';' from missing;".to_string()));
+	let err = sqlite.exec("select 1; selec ';' from t; select 2;").err().unwrap();
+	assert_eq!(err.sql, Some("selec ';' from t;".to_string()));
+
+	sqlite.exec("create table t (a unique);").unwrap();
+	let mut insert = sqlite.prepare("insert into t values (1)").unwrap();
+	insert.execute().unwrap();
+	let err = insert.execute().err().unwrap();
+	assert_eq!(err.code, ErrorCode::Constraint);
+	// SQLITE_CONSTRAINT_UNIQUE
+	assert_eq!(err.extended_code, 2067);
+}
+
+#[test]
+fn sqlite_close_last_reference() {
+	let sqlite = Sqlite::new(":memory:").unwrap();
+	let other = sqlite.clone();
+	let mut stmt = sqlite.prepare("select 1").unwrap();
+	assert!(sqlite.close().is_ok());
+	// Remaining clones and statements keep the connection open
+	assert!(other.exec("select 1").is_ok());
+	assert!(stmt.query().is_ok());
+	drop(stmt);
+	assert!(other.close().is_ok());
+}
+
+#[test]
+fn sqlite_open_modes() {
+	// No more silently created databases for mistyped paths
+	match Sqlite::new_with_mode("no-such-file.db", OpenMode::ReadWrite) {
+		Ok(_) => { panic!("created a database"); },
+		Err(err) => { assert_eq!(err.code, ErrorCode::CantOpen); }
+	}
+
+	let sqlite = Sqlite::new_with_mode("test.db", OpenMode::ReadOnly).unwrap();
+	assert!(sqlite.is_read_only());
+	let err = sqlite.exec("create table t (a);").err().unwrap();
+	assert_eq!(err.code, ErrorCode::ReadOnly);
+
+	let sqlite = Sqlite::new_with_mode("file:test.db?immutable=1", OpenMode::ReadOnly).unwrap();
+	assert!(sqlite.is_read_only());
+	assert_eq!(sqlite.dump_table("fruits".to_string()).unwrap().rows.len(), 5);
+
+	let sqlite = Sqlite::new_with_mode(":memory:", OpenMode::ReadWrite).unwrap();
+	assert!(!sqlite.is_read_only());
+}
+
+#[test]
+fn sqlite_schema_details() {
+	let sqlite = Sqlite::new(":memory:").unwrap();
+	sqlite.exec("create table owners (id integer primary key, name text not null default 'x'); \
+	             create table pets (name, owner references owners on delete cascade, \
+	                                foreign key (name, owner) references other (a, b)); \
+	             create unique index pets_by_owner on pets (owner, name); \
+	             create trigger no_rex before insert on pets when new.name = 'rex' begin select raise(abort, 'no'); end;").unwrap();
+
+	let columns = sqlite.table_info("owners").unwrap();
+	assert_eq!(columns.len(), 2);
+	assert_eq!(columns[0].pk, 1);
+	assert_eq!(columns[1].decl_type, "text");
+	assert!(columns[1].not_null);
+	assert_eq!(columns[1].default, Some("'x'".to_string()));
+
+	let indexes = sqlite.indexes("pets").unwrap();
+	assert_eq!(indexes.len(), 1);
+	assert!(indexes[0].unique);
+	assert_eq!(indexes[0].columns, vec!["owner".to_string(), "name".to_string()]);
+
+	let keys = sqlite.foreign_keys("pets").unwrap();
+	assert_eq!(keys.len(), 2);
+	let to_owners = keys.iter().find(|key| key.table.as_slice() == "owners").unwrap();
+	assert_eq!(to_owners.columns, vec!["owner".to_string()]);
+	assert!(to_owners.to.is_empty());
+	assert_eq!(to_owners.on_delete, "CASCADE");
+	let to_other = keys.iter().find(|key| key.table.as_slice() == "other").unwrap();
+	assert_eq!(to_other.columns, vec!["name".to_string(), "owner".to_string()]);
+
+	assert_eq!(sqlite.triggers("pets").unwrap()[0].0, "no_rex");
+	assert_eq!(sqlite.object_sql("pets_by_owner").unwrap(),
+	           Some("CREATE UNIQUE INDEX pets_by_owner on pets (owner, name)".to_string()));
+}
+
+#[test]
+fn pager_fetches_pages() {
+	let sqlite = Sqlite::new(":memory:").unwrap();
+	sqlite.exec("create table big (n);
+	             create table kv (k, v, primary key (k, v)) without rowid;
+	             with recursive c(i) as (select 1 union all select i + 1 from c limit 10000)
+	             insert into big select i from c;
+	             insert into kv select n % 100, n from big;
+	             create view big_view as select n from big;").unwrap();
+
+	for table in ["big", "kv", "big_view"].iter() {
+		let mut pager = TablePager::new(sqlite.clone(), *table).unwrap();
+		assert_eq!(pager.num_rows(), 10000);
+		// Nothing is read until asked for
+		assert!(pager.get(0).is_none());
+		pager.fetch(0, 20).unwrap();
+		assert!(pager.get(19).is_some());
+		// Walk forward a page at a time, then jump to the end and walk back
+		let mut start = 0;
+		while start < 300 {
+			pager.fetch(start, 20).unwrap();
+			assert!(pager.get(start + 19).is_some());
+			start = start + 20;
+		}
+		pager.fetch(9980, 20).unwrap();
+		assert!(pager.get(9999).is_some());
+		assert!(pager.get(0).is_none());
+		pager.fetch(9900, 20).unwrap();
+		assert!(pager.get(9900).is_some());
+	}
+
+	// Rows come back in key order wherever they are read from
+	let mut pager = TablePager::new(sqlite.clone(), "big").unwrap();
+	pager.fetch(5000, 10).unwrap();
+	assert_eq!(pager.get(5000).unwrap()[1], Value::Integer(5001));
+	pager.fetch(4900, 10).unwrap();
+	assert_eq!(pager.get(4900).unwrap()[1], Value::Integer(4901));
+	pager.fetch(9990, 10).unwrap();
+	assert_eq!(pager.get(9999).unwrap()[1], Value::Integer(10000));
+}